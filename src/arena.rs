@@ -0,0 +1,260 @@
+use std::cmp::Ordering;
+
+use log::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio_util::sync::CancellationToken;
+
+use crate::client::{
+    async_client::AsyncClient,
+    error::ClientError,
+    http,
+    models::{BoardView, InputContent},
+    usage::Pricing,
+    ApiClient,
+};
+use crate::config::ArenaSettings;
+use crate::models::{Direction, Point};
+use crate::snake::Snake;
+
+pub mod tournament;
+
+pub struct Entrant {
+    pub name: String,
+    pub client: AsyncClient,
+    pub pricing: Option<Pricing>,
+}
+
+impl Entrant {
+    pub fn new(name: String, client: Box<dyn ApiClient>) -> Self {
+        Self {
+            name,
+            client: AsyncClient::new(client),
+            pricing: None,
+        }
+    }
+
+    pub fn with_pricing(mut self, pricing: Option<Pricing>) -> Self {
+        self.pricing = pricing;
+        self
+    }
+}
+
+struct Contestant {
+    entrant: usize,
+    snake: Snake,
+    commands: Vec<Direction>,
+    score: u16,
+    alive: bool,
+    survived: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Standing {
+    pub entrant: usize,
+    pub score: u16,
+    pub survived: u32,
+    pub alive: bool,
+}
+
+impl Standing {
+    /// orders standings from the best to the worst:
+    /// survivors first, then by score, then by the number of ticks survived
+    pub fn compare(&self, other: &Standing) -> Ordering {
+        other
+            .alive
+            .cmp(&self.alive)
+            .then(other.score.cmp(&self.score))
+            .then(other.survived.cmp(&self.survived))
+    }
+}
+
+#[derive(Debug)]
+pub struct MatchResult {
+    pub seed: u64,
+    pub ticks: u32,
+    pub standings: Vec<Standing>,
+}
+
+/// Several snakes sharing one board, each of them controlled by its own entrant.
+/// The food sequence is driven by the seed, so matches with the same seed are reproducible.
+pub struct Arena {
+    width: i32,
+    height: i32,
+    max_ticks: u32,
+    seed: u64,
+    rng: StdRng,
+    food: Point,
+    tick: u32,
+    contestants: Vec<Contestant>,
+}
+
+impl Arena {
+    pub fn new(settings: &ArenaSettings, seed: u64, entrants: &[usize]) -> Self {
+        let width = settings.width as i32;
+        let height = settings.height as i32;
+        let slots = entrants.len() as i32 + 1;
+
+        let contestants = entrants
+            .iter()
+            .enumerate()
+            .map(|(i, &entrant)| {
+                let mut snake = Snake::with_direction(Direction::Up);
+                snake.set_head(Point::new(width * (i as i32 + 1) / slots, height / 2));
+                Contestant {
+                    entrant,
+                    snake,
+                    commands: Vec::new(),
+                    score: 0,
+                    alive: true,
+                    survived: 0,
+                }
+            })
+            .collect();
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let food = Point::new(rng.gen_range(0..width), rng.gen_range(0..height));
+
+        Self {
+            width,
+            height,
+            max_ticks: settings.max_ticks,
+            seed,
+            rng,
+            food,
+            tick: 0,
+            contestants,
+        }
+    }
+
+    pub fn play(mut self, entrants: &mut [Entrant]) -> MatchResult {
+        while !self.is_finished() {
+            self.step(entrants);
+        }
+        self.result()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        let alive = self.contestants.iter().filter(|c| c.alive).count();
+        let last_one_standing = self.contestants.len() > 1 && alive <= 1;
+        alive == 0 || last_one_standing || self.tick >= self.max_ticks
+    }
+
+    pub fn step(&mut self, entrants: &mut [Entrant]) {
+        let mut board = BoardView {
+            width: self.width,
+            height: self.height,
+            occupied: self
+                .contestants
+                .iter()
+                .filter(|c| c.alive)
+                .flat_map(|c| c.snake.get_list())
+                .collect(),
+            snake: Vec::new(),
+            heading: None,
+        };
+        // the snakes without commands ask their entrants at the same time
+        let mut requests = Vec::new();
+        for (i, contestant) in self.contestants.iter().enumerate() {
+            if !contestant.alive || !contestant.commands.is_empty() {
+                continue;
+            }
+            board.snake = contestant.snake.get_list().into_iter().collect();
+            board.heading = Some(contestant.snake.get_direction());
+            let head = contestant.snake.get_head();
+            let input = InputContent {
+                snake_head_x: head.x,
+                snake_head_y: head.y,
+                food_x: self.food.x,
+                food_y: self.food.y,
+            };
+            let client = entrants[contestant.entrant].client.clone();
+            let board = board.clone();
+            let task = http::runtime().spawn(async move {
+                client
+                    .snake_commands(input, board, CancellationToken::new())
+                    .await
+            });
+            requests.push((i, task));
+        }
+        for (i, task) in requests {
+            let contestant = &mut self.contestants[i];
+            let name = &entrants[contestant.entrant].name;
+            let result = http::runtime()
+                .block_on(task)
+                .unwrap_or_else(|e| Err(ClientError::Other(e.to_string())));
+            match result {
+                Ok(output) => {
+                    for c in output.commands {
+                        for _ in 0..c.repeat {
+                            contestant.commands.push(c.command.clone());
+                        }
+                    }
+                }
+                Err(e) => error!("{}: {}", name, e),
+            }
+        }
+
+        for contestant in self.contestants.iter_mut().filter(|c| c.alive) {
+            if !contestant.commands.is_empty() {
+                let command = contestant.commands.remove(0);
+                contestant.snake.change_direction(command);
+            }
+            contestant.snake.moving();
+        }
+
+        let crashed: Vec<usize> = (0..self.contestants.len())
+            .filter(|&i| self.contestants[i].alive && self.is_crashed(i))
+            .collect();
+        for i in crashed {
+            self.contestants[i].alive = false;
+        }
+
+        self.tick += 1;
+        for contestant in self.contestants.iter_mut().filter(|c| c.alive) {
+            contestant.survived = self.tick;
+            let head = contestant.snake.get_head();
+            if (head.x, head.y) == (self.food.x, self.food.y) {
+                contestant.score += 1;
+                contestant.snake.grow();
+                self.food = Point::new(
+                    self.rng.gen_range(0..self.width),
+                    self.rng.gen_range(0..self.height),
+                );
+            }
+        }
+    }
+
+    pub fn result(&self) -> MatchResult {
+        let mut standings: Vec<Standing> = self
+            .contestants
+            .iter()
+            .map(|c| Standing {
+                entrant: c.entrant,
+                score: c.score,
+                survived: c.survived,
+                alive: c.alive,
+            })
+            .collect();
+        standings.sort_by(|a, b| a.compare(b));
+
+        MatchResult {
+            seed: self.seed,
+            ticks: self.tick,
+            standings,
+        }
+    }
+
+    fn is_crashed(&self, index: usize) -> bool {
+        let others: Vec<Point> = self
+            .contestants
+            .iter()
+            .enumerate()
+            .filter(|(i, c)| *i != index && c.alive)
+            .flat_map(|(_, c)| c.snake.get_list())
+            .collect();
+        self.contestants[index]
+            .snake
+            .crash((self.width, self.height), &others)
+            .is_some()
+    }
+}
@@ -0,0 +1,267 @@
+use std::cmp::Ordering;
+
+use log::*;
+
+use crate::config::ArenaSettings;
+
+use super::{Arena, Entrant, MatchResult};
+
+const INITIAL_RATING: f64 = 1500.0;
+const K_FACTOR: f64 = 32.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rating {
+    pub name: String,
+    pub elo: f64,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    pub points: u32,
+}
+
+impl Rating {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            elo: INITIAL_RATING,
+            wins: 0,
+            draws: 0,
+            losses: 0,
+            points: 0,
+        }
+    }
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+impl Outcome {
+    fn score(&self) -> f64 {
+        match self {
+            Outcome::Win => 1.0,
+            Outcome::Draw => 0.5,
+            Outcome::Loss => 0.0,
+        }
+    }
+}
+
+/// Round-robin tournament: every pair of entrants plays `rounds` seeds from both sides of the board.
+pub struct Tournament {
+    settings: ArenaSettings,
+    ratings: Vec<Rating>,
+}
+
+impl Tournament {
+    pub fn new(settings: ArenaSettings, entrants: &[Entrant]) -> Self {
+        Self {
+            settings,
+            ratings: entrants
+                .iter()
+                .map(|e| Rating::new(e.name.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn run(mut self, entrants: &mut [Entrant]) -> Vec<Rating> {
+        for a in 0..entrants.len() {
+            for b in (a + 1)..entrants.len() {
+                for round in 0..self.settings.rounds {
+                    let seed = self.settings.seed.wrapping_add(round as u64);
+                    for pair in [[a, b], [b, a]] {
+                        let result = Arena::new(&self.settings, seed, &pair).play(entrants);
+                        info!(
+                            "{} vs {} (seed {}): {:?}",
+                            entrants[pair[0]].name, entrants[pair[1]].name, seed, result.standings
+                        );
+                        self.record(&result);
+                    }
+                }
+            }
+        }
+
+        let mut ratings = self.ratings;
+        ratings.sort_by(|a, b| b.elo.partial_cmp(&a.elo).unwrap_or(Ordering::Equal));
+        ratings
+    }
+
+    fn record(&mut self, result: &MatchResult) {
+        let [first, second] = match result.standings.as_slice() {
+            [first, second] => [first, second],
+            _ => return,
+        };
+        let outcome = match first.compare(second) {
+            Ordering::Less => Outcome::Win,
+            Ordering::Equal => Outcome::Draw,
+            Ordering::Greater => Outcome::Loss,
+        };
+
+        let (a, b) = (first.entrant, second.entrant);
+        let (elo_a, elo_b) = update_elo(self.ratings[a].elo, self.ratings[b].elo, outcome);
+        self.ratings[a].elo = elo_a;
+        self.ratings[b].elo = elo_b;
+        self.ratings[a].points += first.score as u32;
+        self.ratings[b].points += second.score as u32;
+
+        match outcome {
+            Outcome::Win => {
+                self.ratings[a].wins += 1;
+                self.ratings[b].losses += 1;
+            }
+            Outcome::Draw => {
+                self.ratings[a].draws += 1;
+                self.ratings[b].draws += 1;
+            }
+            Outcome::Loss => {
+                self.ratings[a].losses += 1;
+                self.ratings[b].wins += 1;
+            }
+        }
+    }
+}
+
+/// returns new ratings of both players, `outcome` is the result for the first one
+pub fn update_elo(a: f64, b: f64, outcome: Outcome) -> (f64, f64) {
+    let expected_a = 1.0 / (1.0 + 10f64.powf((b - a) / 400.0));
+    let delta = K_FACTOR * (outcome.score() - expected_a);
+    (a + delta, b - delta)
+}
+
+pub fn ranking_table(ratings: &[Rating]) -> String {
+    let name_width = ratings
+        .iter()
+        .map(|r| r.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("Model".len());
+
+    let mut table = format!(
+        "{:>3}  {:<name_width$}  {:>6}  {:>5}  {:>4}  {:>4}  {:>4}  {:>6}\n",
+        "#", "Model", "Elo", "Games", "W", "D", "L", "Points"
+    );
+    for (i, r) in ratings.iter().enumerate() {
+        table.push_str(&format!(
+            "{:>3}  {:<name_width$}  {:>6.0}  {:>5}  {:>4}  {:>4}  {:>4}  {:>6}\n",
+            i + 1,
+            r.name,
+            r.elo,
+            r.games(),
+            r.wins,
+            r.draws,
+            r.losses,
+            r.points
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+    use crate::{
+        client::{
+            error::ClientError,
+            models::{Commands, InputContent, OutputContent},
+            ApiClient,
+        },
+        models::Direction,
+    };
+
+    /// moves straight towards the food, first along x then along y
+    struct GreedyClient;
+
+    impl ApiClient for GreedyClient {
+        fn snake_commands(&mut self, input: InputContent) -> Result<OutputContent, ClientError> {
+            let dx = input.food_x - input.snake_head_x;
+            let dy = input.food_y - input.snake_head_y;
+            let mut commands = Vec::new();
+            if dx != 0 {
+                let command = if dx > 0 {
+                    Direction::Right
+                } else {
+                    Direction::Left
+                };
+                commands.push(Commands {
+                    command,
+                    repeat: dx.abs(),
+                });
+            }
+            if dy != 0 {
+                let command = if dy > 0 {
+                    Direction::Up
+                } else {
+                    Direction::Down
+                };
+                commands.push(Commands {
+                    command,
+                    repeat: dy.abs(),
+                });
+            }
+            Ok(OutputContent { commands })
+        }
+    }
+
+    /// never answers, so the snake keeps going until it hits the wall
+    struct SilentClient;
+
+    impl ApiClient for SilentClient {
+        fn snake_commands(&mut self, _input: InputContent) -> Result<OutputContent, ClientError> {
+            Err(ClientError::Other("no answer".to_string()))
+        }
+    }
+
+    #[rstest]
+    #[case(1500.0, 1500.0, Outcome::Win, 1516.0, 1484.0)]
+    #[case(1500.0, 1500.0, Outcome::Draw, 1500.0, 1500.0)]
+    #[case(1500.0, 1500.0, Outcome::Loss, 1484.0, 1516.0)]
+    fn test_update_elo(
+        #[case] a: f64,
+        #[case] b: f64,
+        #[case] outcome: Outcome,
+        #[case] expected_a: f64,
+        #[case] expected_b: f64,
+    ) {
+        let (new_a, new_b) = update_elo(a, b, outcome);
+        assert!((new_a - expected_a).abs() < 1e-9);
+        assert!((new_b - expected_b).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_same_seed_same_result() {
+        let settings = ArenaSettings::default();
+        let mut entrants = vec![
+            Entrant::new("greedy".to_string(), Box::new(GreedyClient)),
+            Entrant::new("greedy-2".to_string(), Box::new(GreedyClient)),
+        ];
+
+        let first = Arena::new(&settings, 7, &[0, 1]).play(&mut entrants);
+        let second = Arena::new(&settings, 7, &[0, 1]).play(&mut entrants);
+
+        assert_eq!(first.standings, second.standings);
+        assert_eq!(first.ticks, second.ticks);
+    }
+
+    #[test]
+    fn test_tournament_ranking() {
+        let settings = ArenaSettings::default();
+        let mut entrants = vec![
+            Entrant::new("silent".to_string(), Box::new(SilentClient)),
+            Entrant::new("greedy".to_string(), Box::new(GreedyClient)),
+        ];
+
+        let ratings = Tournament::new(settings.clone(), &entrants).run(&mut entrants);
+
+        assert_eq!(ratings[0].name, "greedy");
+        assert_eq!(ratings[0].games(), settings.rounds * 2);
+        assert!(ratings[0].elo > ratings[1].elo);
+    }
+}
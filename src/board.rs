@@ -7,9 +7,16 @@ use crossterm::{
 
 use ratatui::{backend::CrosstermBackend, Terminal};
 
-use crate::{game::Board, models::Point, snake::Snake};
+use crate::{
+    game::{Board, Panels},
+    history::GameRecord,
+    models::Point,
+    registry::Entry,
+    snake::Snake,
+};
 
 mod draw;
+pub mod headless;
 
 use crate::models::{GameState, UIMode};
 
@@ -18,8 +25,14 @@ use self::draw::ui;
 pub struct BoardTUI {
     terminal: Terminal<CrosstermBackend<io::Stdout>>,
     ui_mode: UIMode,
+    size: BoardSize,
+}
+
+/// size of the board in game coordinates, follows the terminal size unless it is fixed
+struct BoardSize {
     width: u16,
     height: u16,
+    fixed: bool,
 }
 
 #[derive(Clone)]
@@ -28,6 +41,18 @@ struct RednerObjects<'a> {
     food: &'a Point,
 }
 
+#[derive(Default)]
+struct ScreenData<'a> {
+    score: u16,
+    status: &'a str,
+    bests: &'a [(String, u16)],
+    high_scores: &'a [GameRecord],
+    notices: &'a [String],
+    menu: &'a [Entry],
+    selected: usize,
+    panels: Option<&'a Panels<'a>>,
+}
+
 impl Board for BoardTUI {
     fn prepare_ui(&mut self) {
         enable_raw_mode().unwrap();
@@ -41,18 +66,24 @@ impl Board for BoardTUI {
             original_hook(panic);
         }));
         self.autoresize();
-        let size = self.terminal.get_frame().size();
-        self.width = size.width;
-        self.height = size.height;
+        if !self.size.fixed {
+            let size = self.terminal.get_frame().size();
+            self.size.width = size.width;
+            self.size.height = size.height;
+        }
     }
 
-    fn render_game(&mut self, snake: &Snake, food: &Point, score: u16) {
-        let render_objects = Some(RednerObjects {
-            snake: snake,
-            food: food,
-        });
+    fn render_game(
+        &mut self,
+        snake: &Snake,
+        food: &Point,
+        score: u16,
+        status: &str,
+        panels: &Panels,
+    ) {
+        let render_objects = Some(RednerObjects { snake, food });
 
-        let board_size = (&mut self.width, &mut self.height);
+        let board_size = &mut self.size;
 
         self.terminal
             .draw(|frame| {
@@ -62,14 +93,19 @@ impl Board for BoardTUI {
                     board_size,
                     &self.ui_mode,
                     GameState::Running,
-                    score,
+                    &ScreenData {
+                        score,
+                        status,
+                        panels: Some(panels),
+                        ..Default::default()
+                    },
                 )
             })
             .unwrap();
     }
 
-    fn render_start_screen(&mut self) {
-        let board_size = (&mut self.width, &mut self.height);
+    fn render_start_screen(&mut self, notices: &[String]) {
+        let board_size = &mut self.size;
 
         self.terminal
             .draw(|frame| {
@@ -79,14 +115,17 @@ impl Board for BoardTUI {
                     board_size,
                     &self.ui_mode,
                     GameState::NotStarted,
-                    0,
+                    &ScreenData {
+                        notices,
+                        ..Default::default()
+                    },
                 )
             })
             .unwrap();
     }
 
-    fn render_game_over(&mut self, score: u16) {
-        let board_size = (&mut self.width, &mut self.height);
+    fn render_game_over(&mut self, score: u16, bests: &[(String, u16)]) {
+        let board_size = &mut self.size;
 
         self.terminal
             .draw(|frame| {
@@ -96,22 +135,49 @@ impl Board for BoardTUI {
                     board_size,
                     &self.ui_mode,
                     GameState::GameOver,
-                    score,
+                    &ScreenData {
+                        score,
+                        bests,
+                        ..Default::default()
+                    },
+                )
+            })
+            .unwrap();
+    }
+
+    fn render_selecting_mode(&mut self, entries: &[Entry], selected: usize, notices: &[String]) {
+        self.terminal
+            .draw(|frame| {
+                ui(
+                    frame,
+                    &None,
+                    &mut self.size,
+                    &self.ui_mode,
+                    GameState::NotStarted,
+                    &ScreenData {
+                        notices,
+                        menu: entries,
+                        selected,
+                        ..Default::default()
+                    },
                 )
             })
             .unwrap();
     }
 
-    fn render_selecting_mode(&mut self) {
+    fn render_high_scores(&mut self, records: &[GameRecord]) {
         self.terminal
             .draw(|frame| {
                 ui(
                     frame,
                     &None,
-                    (&mut self.width, &mut self.height),
+                    &mut self.size,
                     &self.ui_mode,
                     GameState::NotStarted,
-                    0,
+                    &ScreenData {
+                        high_scores: records,
+                        ..Default::default()
+                    },
                 )
             })
             .unwrap();
@@ -131,7 +197,7 @@ impl Board for BoardTUI {
     }
 
     fn get_size(&self) -> (u16, u16) {
-        (self.width, self.height)
+        (self.size.width, self.size.height)
     }
 
     fn autoresize(&mut self) {
@@ -140,12 +206,16 @@ impl Board for BoardTUI {
 }
 
 impl BoardTUI {
-    pub fn new() -> Self {
+    pub fn new(fixed_size: Option<(u16, u16)>) -> Self {
+        let (width, height) = fixed_size.unwrap_or((0, 0));
         Self {
             terminal: Terminal::new(CrosstermBackend::new(stdout())).unwrap(),
             ui_mode: UIMode::Game,
-            width: 0,
-            height: 0,
+            size: BoardSize {
+                width,
+                height,
+                fixed: fixed_size.is_some(),
+            },
         }
     }
 }
@@ -10,61 +10,126 @@ use ratatui::{
     text::Line,
     widgets::{
         canvas::{Canvas, Points},
-        Block, Borders, Paragraph, Widget,
+        Block, Borders, List, ListItem, ListState, Paragraph, Widget, Wrap,
     },
     Frame,
 };
-use strum::IntoEnumIterator;
 use tui_logger::{TuiLoggerLevelOutput, TuiLoggerWidget};
 
 use crate::{
-    models::{self, GameState, Point, UIMode},
+    client::models::Commands,
+    inspector::{Inspection, Inspector, Verdict},
+    lookahead::PlannedPath,
+    models::{GameState, Point, UIMode},
+    reasoning::Reasoning,
     snake::Snake,
 };
 
-use super::RednerObjects;
+use super::{BoardSize, RednerObjects, ScreenData};
 
 pub fn ui(
     frame: &mut Frame,
     render_objects: &Option<RednerObjects>,
-    board_size: (&mut u16, &mut u16),
+    board_size: &mut BoardSize,
     ui_mode: &UIMode,
     game_state: GameState,
-    score: u16,
+    screen_data: &ScreenData,
 ) {
     match ui_mode {
-        UIMode::Game => render_game(frame, render_objects, board_size, game_state, score),
+        UIMode::Game => render_game(frame, render_objects, board_size, game_state, screen_data),
         UIMode::GameWithDebug => {
-            render_game_with_debug(frame, render_objects, board_size, game_state, score)
+            render_game_with_debug(frame, render_objects, board_size, game_state, screen_data)
         }
-        UIMode::SelectingMode => {
+        UIMode::HighScores => {
             let main_layout = main_layout(frame);
 
-            let mut content = vec![Line::from("Select game mode:").bold()];
-            content.push(Line::from("1. Player mode"));
-
-            for (i, provider) in models::Provider::iter()
-                .enumerate()
-                .map(|(i, v)| (i + 2, v))
-            {
-                let line = format!("{}. {}", i, provider);
-                content.push(Line::from(line));
+            let mut content = vec![Line::from("High scores").bold(), Line::from("")];
+            if screen_data.high_scores.is_empty() {
+                content.push(Line::from("No games played yet"));
             }
+            for (i, record) in screen_data.high_scores.iter().enumerate() {
+                let player = match (&record.provider, &record.model) {
+                    (Some(provider), Some(model)) => format!("{} ({})", provider, model),
+                    (Some(provider), None) => provider.clone(),
+                    _ => "Player".to_string(),
+                };
+                content.push(Line::from(format!(
+                    "{:>2}. {:>4}  {:<28} {:>4}s  {}",
+                    i + 1,
+                    record.score,
+                    player,
+                    record.duration_secs,
+                    record.date
+                )));
+            }
+            content.push(Line::from(""));
+            content.push(Line::from("Press any key to go back".bold()));
 
             frame.render_widget(Block::bordered().title("Snake game"), main_layout[0]);
             let paragraph = Paragraph::new(content).centered();
-            let area = centered_rect(60, 20, main_layout[0]);
+            let area = centered_rect(80, 60, main_layout[0]);
             frame.render_widget(paragraph, area);
         }
+        UIMode::SelectingMode => {
+            let main_layout = main_layout(frame);
+            frame.render_widget(Block::bordered().title("Snake game"), main_layout[0]);
+
+            let area = centered_rect(60, 60, main_layout[0]);
+            let notices = notice_lines(screen_data.notices);
+            let layout = Layout::vertical([
+                Constraint::Length(2),
+                Constraint::Min(3),
+                Constraint::Length(notices.len() as u16),
+                Constraint::Length(1),
+            ])
+            .split(area);
+
+            frame.render_widget(
+                Paragraph::new(Line::from("Select game mode:").bold()).centered(),
+                layout[0],
+            );
+
+            let mut items = vec![ListItem::new("1. Player mode")];
+            for (i, entry) in screen_data.menu.iter().enumerate() {
+                let number = i + 2;
+                let key = if number <= 9 {
+                    format!("{}.", number)
+                } else {
+                    "  ".to_string()
+                };
+                let details = match &entry.model {
+                    Some(model) => format!("{}, {}", entry.provider, model),
+                    None => "not configured".to_string(),
+                };
+                let item = ListItem::new(format!("{} {} ({})", key, entry.name, details));
+                items.push(if entry.is_available() {
+                    item
+                } else {
+                    item.dark_gray()
+                });
+            }
+            let list = List::new(items)
+                .highlight_style(Style::default().reversed())
+                .highlight_symbol("> ");
+            let mut state = ListState::default().with_selected(Some(screen_data.selected));
+            frame.render_stateful_widget(list, layout[1], &mut state);
+
+            frame.render_widget(Paragraph::new(notices).centered(), layout[2]);
+            frame.render_widget(
+                Paragraph::new(Line::from("arrows to move, enter to select").dark_gray())
+                    .centered(),
+                layout[3],
+            );
+        }
     }
 }
 
 fn render_game(
     frame: &mut Frame,
     render_objects: &Option<RednerObjects>,
-    board_size: (&mut u16, &mut u16),
+    board_size: &mut BoardSize,
     game_state: GameState,
-    score: u16,
+    screen_data: &ScreenData,
 ) {
     let main_layout = main_layout(frame);
 
@@ -72,7 +137,7 @@ fn render_game(
         frame,
         game_state,
         render_objects,
-        score,
+        screen_data,
         board_size,
         main_layout[0],
         main_layout[1],
@@ -83,11 +148,12 @@ fn render_game_state(
     frame: &mut Frame,
     game_state: GameState,
     render_objects: &Option<RednerObjects>,
-    score: u16,
-    board_size: (&mut u16, &mut u16),
+    screen_data: &ScreenData,
+    board_size: &mut BoardSize,
     canvas_layout: Rect,
     score_layout: Rect,
 ) {
+    let canvas_layout = fit_board(canvas_layout, board_size);
     let new_size = new_size_board(&canvas_layout, board_size);
     let mut content = vec![Line::from("Press any key to start".bold())];
     content.push(Line::from("'q' to quit".bold()));
@@ -96,34 +162,52 @@ fn render_game_state(
     match game_state {
         GameState::Running => {
             if let Some(objects) = render_objects {
-                frame.render_widget(score_block(score), score_layout);
                 frame.render_widget(
-                    map_canvas(&objects.snake, &objects.food, new_size),
+                    score_block(screen_data.score, screen_data.status),
+                    score_layout,
+                );
+                frame.render_widget(
+                    map_canvas(
+                        objects.snake,
+                        objects.food,
+                        screen_data.panels.and_then(|p| p.path),
+                        new_size,
+                    ),
                     canvas_layout,
                 );
             }
             return;
         }
-        GameState::NotStarted => {}
+        GameState::NotStarted => {
+            content.push(Line::from("'h' to show high scores".bold()));
+            content.extend(notice_lines(screen_data.notices));
+        }
         GameState::GameOver => {
             content.push(Line::from(""));
             content.push(Line::from(
-                format!("Game over! your score was: {}", score).bold(),
+                format!("Game over! your score was: {}", screen_data.score).bold(),
             ));
+            if !screen_data.bests.is_empty() {
+                content.push(Line::from(""));
+                content.push(Line::from("Best scores:"));
+                for (provider, best) in screen_data.bests {
+                    content.push(Line::from(format!("{}: {}", provider, best)));
+                }
+            }
         }
     }
     frame.render_widget(Block::bordered().title("Snake game"), canvas_layout);
     let paragraph = Paragraph::new(content).centered();
-    let area = centered_rect(60, 20, canvas_layout);
+    let area = centered_rect(80, 60, canvas_layout);
     frame.render_widget(paragraph, area);
 }
 
 fn render_game_with_debug(
     frame: &mut Frame,
     render_objects: &Option<RednerObjects>,
-    board_size: (&mut u16, &mut u16),
+    board_size: &mut BoardSize,
     game_state: GameState,
-    score: u16,
+    screen_data: &ScreenData,
 ) {
     let main_layout = main_layout(frame);
     let game_and_debug_layout = Layout::new(
@@ -136,7 +220,7 @@ fn render_game_with_debug(
         frame,
         game_state,
         render_objects,
-        score,
+        screen_data,
         board_size,
         game_and_debug_layout[0],
         main_layout[1],
@@ -155,28 +239,201 @@ fn render_game_with_debug(
         .style_trace(Style::default().fg(Color::Magenta))
         .style_info(Style::default().fg(Color::White));
 
-    frame.render_widget(log, game_and_debug_layout[1]);
+    let Some(panels) = screen_data.panels else {
+        frame.render_widget(log, game_and_debug_layout[1]);
+        return;
+    };
+    let mut area = game_and_debug_layout[1];
+    if !panels.reasoning.thoughts.is_empty() {
+        let debug_layout =
+            Layout::vertical([Constraint::Percentage(50), Constraint::Min(5)]).split(area);
+        frame.render_widget(reasoning_panel(panels.reasoning), debug_layout[0]);
+        area = debug_layout[1];
+    }
+    if panels.log {
+        frame.render_widget(log, area);
+    } else {
+        render_inspector(frame, panels.inspector, area);
+    }
+}
+
+/// Requests of the game one per line, or the selected one with its whole prompt and response
+fn render_inspector(frame: &mut Frame, inspector: &Inspector, area: Rect) {
+    if let (true, Some(request)) = (inspector.expanded, inspector.selected()) {
+        let title = format!("Request #{} (enter to close, arrows to scroll)", request.id);
+        let paragraph = Paragraph::new(inspection_lines(request))
+            .block(Block::bordered().title(title))
+            .wrap(Wrap { trim: false })
+            .scroll((inspector.scroll, 0));
+        frame.render_widget(paragraph, area);
+        return;
+    }
+
+    let items: Vec<ListItem> = inspector
+        .requests
+        .iter()
+        .map(|request| {
+            let elapsed = match request.elapsed {
+                Some(elapsed) => format!("{} ms", elapsed.as_millis()),
+                None => "-".to_string(),
+            };
+            let line = format!(
+                "{} #{} ({},{})->({},{}) {} | {}, {} tok | {}",
+                request.sent_at,
+                request.id,
+                request.input.snake_head_x,
+                request.input.snake_head_y,
+                request.input.food_x,
+                request.input.food_y,
+                plan(&request.commands),
+                elapsed,
+                request.usage.prompt_tokens + request.usage.completion_tokens,
+                request.verdict
+            );
+            ListItem::new(line).style(verdict_style(&request.verdict))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::bordered().title("Requests (arrows, enter to expand, l for the log)"))
+        .highlight_style(Style::default().reversed());
+    let mut state = ListState::default().with_selected(Some(inspector.selected));
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn inspection_lines(request: &Inspection) -> Vec<Line<'_>> {
+    let input = &request.input;
+    let usage = &request.usage;
+    let mut lines = vec![
+        Line::from(format!(
+            "{} at {}: {}",
+            request.provider, request.sent_at, request.verdict
+        ))
+        .style(verdict_style(&request.verdict)),
+        Line::from(format!(
+            "State: head {},{}, food {},{}",
+            input.snake_head_x, input.snake_head_y, input.food_x, input.food_y
+        )),
+        Line::from(format!("Commands: {}", plan(&request.commands))),
+        Line::from(format!(
+            "Latency: {} ms, tokens: {} in / {} out",
+            request.elapsed.map_or(0, |e| e.as_millis()),
+            usage.prompt_tokens,
+            usage.completion_tokens
+        )),
+    ];
+    for repair in &request.transcript.repairs {
+        lines.push(Line::from(format!("Repaired: {}", repair)).yellow());
+    }
+    for (title, text) in [
+        ("Prompt", &request.transcript.prompt),
+        ("Response", &request.transcript.response),
+    ] {
+        lines.push(Line::from(""));
+        lines.push(Line::from(title).bold());
+        lines.extend(text.lines().map(Line::from));
+    }
+    if !request.transcript.tool_calls.is_empty() {
+        lines.push(Line::from(""));
+        lines.push(Line::from("Tool calls").bold());
+        lines.extend(
+            request
+                .transcript
+                .tool_calls
+                .iter()
+                .map(|call| Line::from(call.as_str())),
+        );
+    }
+    lines
+}
+
+/// e.g. "up 3, left 2"
+fn plan(commands: &[Commands]) -> String {
+    if commands.is_empty() {
+        return "-".to_string();
+    }
+    commands
+        .iter()
+        .map(|c| format!("{:?} {}", c.command, c.repeat).to_lowercase())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn verdict_style(verdict: &Verdict) -> Style {
+    let color = match verdict {
+        Verdict::Pending => Color::White,
+        Verdict::Applied => Color::Green,
+        Verdict::Repaired => Color::Yellow,
+        Verdict::Discarded => Color::DarkGray,
+        Verdict::Fatal(_) => Color::Red,
+    };
+    Style::default().fg(color)
+}
+
+/// reasoning behind the plans, the newest first, scrolled with page up and page down
+fn reasoning_panel(reasoning: &Reasoning) -> Paragraph<'_> {
+    let mut lines = Vec::new();
+    for thought in &reasoning.thoughts {
+        lines.push(Line::from(thought.title()).bold());
+        lines.extend(
+            thought
+                .text
+                .lines()
+                .map(|line| Line::from(line).dark_gray()),
+        );
+        lines.push(Line::from(""));
+    }
+    // scrolled by the lines of the text, so the last one can always be reached when they wrap
+    let lines: Vec<Line> = lines.into_iter().skip(reasoning.scroll as usize).collect();
+    Paragraph::new(lines)
+        .block(Block::bordered().title("Reasoning (PgUp/PgDn)"))
+        .wrap(Wrap { trim: false })
+}
+
+fn notice_lines(notices: &[String]) -> Vec<Line<'_>> {
+    if notices.is_empty() {
+        return Vec::new();
+    }
+    let mut lines = vec![Line::from(""), Line::from("Config problems:".red().bold())];
+    for notice in notices {
+        lines.push(Line::from(notice.as_str().red()));
+    }
+    lines
 }
 
 fn main_layout(frame: &Frame) -> Rc<[Rect]> {
-    let main_layout = Layout::new(
+    Layout::new(
         Direction::Vertical,
         [Constraint::Min(0), Constraint::Length(1)],
     )
-    .split(frame.size());
-
-    main_layout
+    .split(frame.size())
 }
 
-fn score_block(score: u16) -> impl Widget {
-    Block::new()
-        .title(format!("Score: {}", score))
-        .title_alignment(Alignment::Center)
+fn score_block(score: u16, status: &str) -> impl Widget {
+    let title = if status.is_empty() {
+        format!("Score: {}", score)
+    } else {
+        format!("Score: {} | {}", score, status)
+    };
+    Block::new().title(title).title_alignment(Alignment::Center)
 }
 
-fn map_canvas(snake: &Snake, food: &Point, canvas_size: (u16, u16)) -> impl Widget {
+/// `path` of the queued commands is drawn dimmed under the snake, with a red cell where it would crash
+fn map_canvas(
+    snake: &Snake,
+    food: &Point,
+    path: Option<&PlannedPath>,
+    canvas_size: (u16, u16),
+) -> impl Widget {
     let snake_shape = SnakeShape::new(snake.get_list());
     let food = food.clone();
+    let coords = |points: &[Point]| -> Vec<(f64, f64)> {
+        points.iter().map(|p| (p.x as f64, p.y as f64)).collect()
+    };
+    let path_cells = path.map(|p| coords(&p.cells)).unwrap_or_default();
+    let crash = path
+        .and_then(|p| p.crash.as_ref())
+        .map(|p| coords(std::slice::from_ref(p)))
+        .unwrap_or_default();
 
     Canvas::default()
         .block(
@@ -187,10 +444,18 @@ fn map_canvas(snake: &Snake, food: &Point, canvas_size: (u16, u16)) -> impl Widg
         )
         .marker(Marker::HalfBlock)
         .paint(move |ctx| {
+            ctx.draw(&Points {
+                coords: &path_cells,
+                color: Color::DarkGray,
+            });
             ctx.draw(&snake_shape);
             ctx.draw(&Points {
                 coords: &[(food.x as f64, food.y as f64)],
                 color: Color::Green,
+            });
+            ctx.draw(&Points {
+                coords: &crash,
+                color: Color::Red,
             })
         })
         .x_bounds([0.0, canvas_size.0 as f64])
@@ -214,17 +479,37 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     .split(popup_layout[1])[1]
 }
 
-fn new_size_board(main_layout: &Rect, board_size: (&mut u16, &mut u16)) -> (u16, u16) {
+fn new_size_board(main_layout: &Rect, board_size: &mut BoardSize) -> (u16, u16) {
+    if board_size.fixed {
+        return (board_size.width, board_size.height);
+    }
     let new_size = terminal_size_to_board_size((main_layout.width, main_layout.height));
 
-    (*board_size.0, *board_size.1) = new_size;
+    (board_size.width, board_size.height) = new_size;
     new_size
 }
 
+/// shrinks the canvas area to the fixed board size, keeping it centered
+fn fit_board(area: Rect, board_size: &BoardSize) -> Rect {
+    if !board_size.fixed {
+        return area;
+    }
+    // + 2 cos of the borders, every terminal row holds two board rows
+    let width = (board_size.width + 2).min(area.width);
+    let height = (board_size.height.div_ceil(2) + 2).min(area.height);
+
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
 fn terminal_size_to_board_size(terminal_size: (u16, u16)) -> (u16, u16) {
     (
         // - 2 cos of the borders
-        (terminal_size.0 - 2) as u16,
-        ((terminal_size.1 - 2) * 2) as u16,
+        terminal_size.0 - 2,
+        (terminal_size.1 - 2) * 2,
     )
 }
@@ -1,17 +1,19 @@
 use std::collections::LinkedList;
 
-use ratatui::{style::Color, widgets::canvas::{Painter, Shape}};
+use ratatui::{
+    style::Color,
+    widgets::canvas::{Painter, Shape},
+};
 
 use crate::models::Point;
 
-
 pub struct SnakeShape {
     list: LinkedList<Point>,
 }
 
 impl SnakeShape {
     pub fn new(list: LinkedList<Point>) -> Self {
-        Self { list: list }
+        Self { list }
     }
 }
 
@@ -0,0 +1,67 @@
+use crate::{
+    game::{Board, Panels},
+    history::GameRecord,
+    models::{Point, UIMode},
+    registry::Entry,
+    snake::Snake,
+};
+
+/// Board without any output, used to run games without a terminal
+pub struct HeadlessBoard {
+    ui_mode: UIMode,
+    width: u16,
+    height: u16,
+}
+
+impl HeadlessBoard {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            ui_mode: UIMode::Game,
+            width,
+            height,
+        }
+    }
+}
+
+impl Board for HeadlessBoard {
+    fn prepare_ui(&mut self) {}
+
+    fn render_game(
+        &mut self,
+        _snake: &Snake,
+        _food: &Point,
+        _score: u16,
+        _status: &str,
+        _panels: &Panels,
+    ) {
+        // nothing renders the log widget, so records are flushed to the log file here
+        tui_logger::move_events();
+    }
+
+    fn render_start_screen(&mut self, _notices: &[String]) {}
+
+    fn render_game_over(&mut self, _score: u16, _bests: &[(String, u16)]) {}
+
+    fn render_selecting_mode(&mut self, _entries: &[Entry], _selected: usize, _notices: &[String]) {
+    }
+
+    fn render_high_scores(&mut self, _records: &[GameRecord]) {}
+
+    fn clean_up(&mut self) {
+        tui_logger::move_events();
+    }
+
+    fn get_size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
+    fn update_mode(&mut self, mode: UIMode) {
+        self.ui_mode = mode;
+    }
+
+    fn get_mode(&self) -> UIMode {
+        self.ui_mode.clone()
+    }
+
+    fn autoresize(&mut self) {}
+}
@@ -0,0 +1,83 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use log::LevelFilter;
+
+#[derive(Parser)]
+#[command(version, about = "A snake game controlled by the player or by an LLM")]
+pub struct Cli {
+    /// Path to the config file
+    #[arg(short, long, default_value = "config.yaml")]
+    pub config: PathBuf,
+
+    /// Start directly in LLM mode controlled by the profile, e.g. groq or ollama
+    #[arg(short, long)]
+    pub provider: Option<String>,
+
+    /// Model used by the profile instead of the configured one
+    #[arg(long, requires = "provider")]
+    pub model: Option<String>,
+
+    /// Seed of the food sequence, random for every game by default
+    #[arg(short, long)]
+    pub seed: Option<u64>,
+
+    /// Board width, fills the terminal by default
+    #[arg(long)]
+    pub width: Option<u16>,
+
+    /// Board height, fills the terminal by default
+    #[arg(long)]
+    pub height: Option<u16>,
+
+    /// Duration of a game tick in milliseconds
+    #[arg(short, long, default_value_t = 30)]
+    pub tick_rate: u64,
+
+    /// Play a single game without the terminal UI and print the result
+    #[arg(long, requires = "provider")]
+    pub headless: bool,
+
+    /// Wait for the queued commands to run out before asking the model again
+    #[arg(long)]
+    pub no_lookahead: bool,
+
+    /// Always ask the models, even profiles with `cache: true` don't use the response cache
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Log level: off, error, warn, info, debug or trace
+    #[arg(long, default_value = "trace", value_parser = parse_level)]
+    pub log_level: LevelFilter,
+
+    /// Append log records to the file
+    #[arg(long)]
+    pub log_file: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub action: Option<Action>,
+}
+
+#[derive(Subcommand)]
+pub enum Action {
+    /// Play one headless match between all configured providers
+    Arena,
+    /// Play a round-robin tournament and print the Elo ranking table
+    Tournament,
+}
+
+impl Cli {
+    /// board size when it is fixed from the command line, headless games always need one
+    pub fn board_size(&self) -> Option<(u16, u16)> {
+        match (self.width, self.height) {
+            (None, None) if !self.headless => None,
+            (width, height) => Some((width.unwrap_or(60), height.unwrap_or(40))),
+        }
+    }
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    level
+        .parse()
+        .map_err(|_| format!("unknown log level: {}", level))
+}
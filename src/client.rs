@@ -1,13 +1,104 @@
+use tokio_util::sync::CancellationToken;
+
 use crate::client::models::InputContent;
 
-use self::models::OutputContent;
+use self::{
+    cache::CacheStats,
+    correction::CorrectionStats,
+    ensemble::MemberStats,
+    error::ClientError,
+    fallback::Switch,
+    few_shot::PlanStats,
+    models::{BoardView, OutputContent},
+    transcript::Transcript,
+    usage::Usage,
+};
 
+pub mod anthropic;
+pub mod async_client;
+pub mod bot;
+pub mod cache;
+pub mod chat;
+pub mod correction;
+pub mod ensemble;
+pub mod error;
+pub mod fallback;
+pub mod few_shot;
+pub mod gemini;
+pub mod grammar;
 pub mod groq;
+pub mod http;
+pub mod llama_cpp;
+pub mod memory;
 pub mod models;
 pub mod ollama;
+pub mod prompt;
+pub mod reasoning;
+pub mod repair;
+pub mod stream;
+pub mod tools;
+pub mod transcript;
+pub mod usage;
+
+pub trait ApiClient: Send {
+    fn snake_commands(&mut self, input: InputContent) -> Result<OutputContent, ClientError>;
+
+    /// board of the next request, only clients that inspect the board use it
+    fn observe_board(&mut self, _board: &BoardView) {}
+
+    /// the requests stop with an error when the token is cancelled
+    fn set_cancellation(&mut self, _token: CancellationToken) {}
+
+    fn correction_stats(&self) -> CorrectionStats {
+        CorrectionStats::default()
+    }
 
-pub trait ApiClient {
-    fn snake_commands(&mut self, input: InputContent) -> Result<OutputContent, String>;
+    /// accuracy of the answered plans, empty for clients that don't track it
+    fn plan_stats(&self) -> PlanStats {
+        PlanStats::default()
+    }
+
+    /// answers taken from the response cache, empty for clients without one
+    fn cache_stats(&self) -> CacheStats {
+        CacheStats::default()
+    }
+
+    /// agreement of the members with the chosen plans, empty for single models
+    fn member_stats(&self) -> Vec<(String, MemberStats)> {
+        Vec::new()
+    }
+
+    /// changes of the active provider since the last call, only fallback chains switch
+    fn take_switches(&mut self) -> Vec<Switch> {
+        Vec::new()
+    }
+
+    /// reasoning the model wrote before its last answer, only reasoning models write it
+    fn take_reasoning(&mut self) -> Option<String> {
+        None
+    }
+
+    /// prompt and answer of the last request, None for clients that don't call a model themselves
+    fn take_transcript(&mut self) -> Option<Transcript> {
+        None
+    }
+
+    /// tokens and latency of all calls so far
+    fn usage(&self) -> Usage {
+        Usage::default()
+    }
+
+    /// Same as snake_commands, but passes the commands to `on_commands` as soon as they are parsed.
+    /// Clients without streaming pass the whole output at once
+    fn stream_snake_commands(
+        &mut self,
+        input: InputContent,
+        on_commands: &mut dyn FnMut(OutputContent),
+    ) -> Result<OutputContent, ClientError> {
+        let output = self.snake_commands(input)?;
+        on_commands(output.clone());
+        Ok(output)
+    }
 }
 
 #[cfg(test)]
@@ -211,11 +302,12 @@ mod tests {
 
     #[apply(test_client)]
     fn test_groq(#[case] input: InputContent, #[case] expected_output: OutputContent) {
-        use super::groq::GroqClient;
+        use super::{chat::ChatClient, groq::GroqApi};
 
         let groq_cfg = get_groqclient_cfg();
 
-        let mut client = GroqClient::new(groq_cfg.url, groq_cfg.token);
+        let mut client =
+            ChatClient::new(GroqApi::new(groq_cfg.url, groq_cfg.token, groq_cfg.model));
         let res = client.snake_commands(input);
 
         if let Ok(res) = res {
@@ -230,7 +322,7 @@ mod tests {
     }
 
     fn get_groqclient_cfg() -> config::TokenClient {
-        let config = crate::config::parse();
+        let config = crate::config::parse(std::path::Path::new("config.yaml")).unwrap();
         if let Some(client_cfg) = config.groq_client {
             client_cfg
         } else {
@@ -240,11 +332,11 @@ mod tests {
 
     #[apply(test_client)]
     fn test_ollama(#[case] input: InputContent, #[case] expected_output: OutputContent) {
-        use super::ollama::OllamaClient;
+        use super::{chat::ChatClient, ollama::OllamaApi};
 
         let ollama_cfg = get_ollama_cfg();
 
-        let mut client = OllamaClient::new(ollama_cfg.url, ollama_cfg.model);
+        let mut client = ChatClient::new(OllamaApi::new(ollama_cfg.url, ollama_cfg.model));
         let res = client.snake_commands(input);
 
         if let Ok(res) = res {
@@ -259,7 +351,7 @@ mod tests {
     }
 
     fn get_ollama_cfg() -> config::Client {
-        let config = crate::config::parse();
+        let config = crate::config::parse(std::path::Path::new("config.yaml")).unwrap();
         if let Some(client_cfg) = config.ollama_client {
             client_cfg
         } else {
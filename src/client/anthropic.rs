@@ -0,0 +1,272 @@
+use std::time::Duration;
+
+use reqwest::{Client, RequestBuilder};
+use serde_derive::{Deserialize, Serialize};
+
+use super::{
+    chat::{self, ChatApi},
+    error::ClientError,
+    http::BodyReader,
+    models::{Message, Role},
+    stream::{self, Streamed},
+    usage::Tokens,
+};
+
+/// context size of the Claude models
+const DEFAULT_CONTEXT_TOKENS: usize = 200_000;
+/// longest answer, a plan is much shorter
+const MAX_ANSWER_TOKENS: u32 = 1024;
+const API_VERSION: &str = "2023-06-01";
+/// start of the answer written for the model, it can only continue the JSON object
+const PREFILL: &str = "{";
+
+#[derive(Serialize, Deserialize, Debug)]
+struct MessagesRequest {
+    model: String,
+    max_tokens: u32,
+    system: String,
+    /// only user and assistant messages, the system prompt is a field of its own
+    messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    stream: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+    stop_reason: Option<String>,
+    usage: Option<MessagesUsage>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct MessagesUsage {
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+impl MessagesResponse {
+    /// text of the answer, an answer cut off or refused has no usable plan
+    fn text(&self) -> Result<String, String> {
+        match self.stop_reason.as_deref() {
+            Some("max_tokens") => return Err("The answer was cut off at max_tokens".to_string()),
+            Some("refusal") => return Err("The model refused to answer".to_string()),
+            _ => {}
+        }
+        Ok(self
+            .content
+            .iter()
+            .filter(|block| block.kind == "text")
+            .map(|block| block.text.as_str())
+            .collect())
+    }
+
+    fn tokens(&self) -> Option<Tokens> {
+        self.usage.as_ref().map(|usage| Tokens {
+            prompt_tokens: usage.input_tokens,
+            completion_tokens: usage.output_tokens,
+        })
+    }
+}
+
+/// Anthropic Messages API
+pub struct AnthropicApi {
+    url: String,
+    token: String,
+    model: String,
+    timeout: Option<Duration>,
+    temperature: Option<f32>,
+    prefill: Option<String>,
+}
+
+impl AnthropicApi {
+    pub fn new(url: String, token: String, model: String) -> Self {
+        Self {
+            url,
+            token,
+            model,
+            timeout: None,
+            temperature: None,
+            prefill: Some(PREFILL.to_string()),
+        }
+    }
+
+    fn prefill(&self) -> &str {
+        self.prefill.as_deref().unwrap_or("")
+    }
+}
+
+impl ChatApi for AnthropicApi {
+    const CONTEXT_TOKENS: usize = DEFAULT_CONTEXT_TOKENS;
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn set_temperature(&mut self, temperature: f32) {
+        self.temperature = Some(temperature);
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    /// The API has no answer schema, the answer is started with `{` for the model instead
+    /// so it can't write text before the JSON
+    fn set_structured_output(&mut self, enabled: bool) {
+        self.prefill = enabled.then(|| PREFILL.to_string());
+    }
+
+    /// Sends the messages with the system prompt moved to its own field
+    /// and the prefill as the start of the answer
+    fn request(
+        &self,
+        client: &Client,
+        messages: &[Message],
+        stream: bool,
+    ) -> Result<RequestBuilder, String> {
+        let (system, mut messages): (Vec<Message>, Vec<Message>) = messages
+            .iter()
+            .cloned()
+            .partition(|m| m.role == Role::System.as_string());
+        if let Some(prefill) = &self.prefill {
+            messages.push(Message {
+                role: Role::Assistant.as_string(),
+                content: prefill.clone(),
+            });
+        }
+        let body = chat::body(&MessagesRequest {
+            model: self.model.clone(),
+            max_tokens: MAX_ANSWER_TOKENS,
+            system: system
+                .into_iter()
+                .map(|m| m.content)
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            messages,
+            temperature: self.temperature,
+            stream,
+        })?;
+
+        let mut request = client
+            .post(&self.url)
+            .header("x-api-key", &self.token)
+            .header("anthropic-version", API_VERSION)
+            .header("content-type", "application/json");
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+        Ok(request.body(body))
+    }
+
+    /// the answer starts with the prefill
+    fn read_response(&self, body: &str) -> Result<(String, Option<Tokens>), String> {
+        let resp: MessagesResponse = match serde_json::from_str(body) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(format!("Failed to parse response body {}", e));
+            }
+        };
+        Ok((format!("{}{}", self.prefill(), resp.text()?), resp.tokens()))
+    }
+
+    fn read_stream(
+        &self,
+        body: BodyReader,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<Streamed, ClientError> {
+        on_chunk(self.prefill());
+        let mut streamed = stream::read_messages_sse(body, &mut *on_chunk)?;
+        streamed.content = format!("{}{}", self.prefill(), streamed.content);
+        Ok(streamed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::client::{
+        chat::ChatClient,
+        http::stand_in_server,
+        models::{BoardView, Commands, InputContent},
+        prompt::PromptTemplate,
+        ApiClient,
+    };
+    use crate::models::Direction;
+
+    #[test]
+    fn test_messages_round_trip() {
+        let (url, server) = stand_in_server(
+            "/v1/messages",
+            include_str!("fixtures/anthropic/response.json"),
+            1,
+        );
+        let template = PromptTemplate {
+            name: "test".to_string(),
+            system: "Play snake.".to_string(),
+            user: "Head at {{snake_head_x}},{{snake_head_y}}, food at {{food_x}},{{food_y}}."
+                .to_string(),
+            examples: Vec::new(),
+        };
+        let mut client = ChatClient::new(AnthropicApi::new(
+            url,
+            "test-key".to_string(),
+            "claude-3-5-haiku-latest".to_string(),
+        ))
+        .with_template(template)
+        .with_temperature(0.5);
+        client.observe_board(&BoardView {
+            width: 10,
+            height: 10,
+            occupied: Vec::new(),
+            snake: Vec::new(),
+            heading: None,
+        });
+
+        let output = client
+            .snake_commands(InputContent {
+                snake_head_x: 5,
+                snake_head_y: 5,
+                food_x: 5,
+                food_y: 8,
+            })
+            .unwrap();
+
+        assert_eq!(
+            output.commands,
+            vec![Commands {
+                command: Direction::Up,
+                repeat: 3
+            }]
+        );
+        assert_eq!(client.usage().prompt_tokens, 25);
+        let (headers, body) = server.join().unwrap().remove(0);
+        assert!(headers.contains(&"x-api-key: test-key".to_string()));
+        assert!(headers.contains(&format!("anthropic-version: {}", API_VERSION)));
+        let body: Value = serde_json::from_str(&body).unwrap();
+        let expected: Value =
+            serde_json::from_str(include_str!("fixtures/anthropic/request.json")).unwrap();
+        assert_eq!(body, expected);
+    }
+
+    #[test]
+    fn test_answer_cut_off() {
+        let mut response: MessagesResponse =
+            serde_json::from_str(include_str!("fixtures/anthropic/response.json")).unwrap();
+        assert!(response.text().is_ok());
+
+        response.stop_reason = Some("max_tokens".to_string());
+
+        assert!(response.text().is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    ensemble::MemberStats,
+    error::ClientError,
+    fallback::Switch,
+    models::{BoardView, InputContent, OutputContent},
+    transcript::Transcript,
+    usage::Usage,
+    ApiClient,
+};
+
+/// what a request returned and cost
+pub struct Outcome {
+    pub result: Result<OutputContent, ClientError>,
+    pub usage: Usage,
+    /// providers the client switched to while answering
+    pub switches: Vec<Switch>,
+    pub reasoning: Option<String>,
+    pub transcript: Option<Transcript>,
+    /// agreement of the ensemble members in this request
+    pub members: Vec<(String, MemberStats)>,
+}
+
+impl Outcome {
+    pub fn failed(error: ClientError) -> Self {
+        Self {
+            result: Err(error),
+            usage: Usage::default(),
+            switches: Vec::new(),
+            reasoning: None,
+            transcript: None,
+            members: Vec::new(),
+        }
+    }
+}
+
+/// ApiClient that can be shared by async tasks. The client runs on the blocking threads
+/// of the runtime, requests of the same client wait for each other,
+/// requests of different clients run at the same time
+#[derive(Clone)]
+pub struct AsyncClient {
+    inner: Arc<Mutex<Box<dyn ApiClient>>>,
+}
+
+impl AsyncClient {
+    pub fn new(client: Box<dyn ApiClient>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(client)),
+        }
+    }
+
+    /// the client for the stats, waits for its running request
+    pub fn lock(&self) -> MutexGuard<'_, Box<dyn ApiClient>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub async fn snake_commands(
+        &self,
+        input: InputContent,
+        board: BoardView,
+        cancel: CancellationToken,
+    ) -> Result<OutputContent, ClientError> {
+        self.stream_snake_commands(input, board, cancel, |_| {})
+            .await
+            .result
+    }
+
+    /// Calls the client with the board and the cancellation token of the request,
+    /// returns the result with the usage of the calls, the switches of the provider,
+    /// the reasoning and transcript of the model and the agreement of the ensemble members
+    pub async fn stream_snake_commands(
+        &self,
+        input: InputContent,
+        board: BoardView,
+        cancel: CancellationToken,
+        mut on_commands: impl FnMut(OutputContent) + Send + 'static,
+    ) -> Outcome {
+        if cancel.is_cancelled() {
+            return Outcome::failed(ClientError::Cancelled);
+        }
+        let inner = self.inner.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            let mut client = inner.lock().unwrap_or_else(|e| e.into_inner());
+            let (before, members_before) = (client.usage(), client.member_stats());
+            client.observe_board(&board);
+            client.set_cancellation(cancel);
+            let result = client.stream_snake_commands(input, &mut on_commands);
+            Outcome {
+                result,
+                usage: client.usage().since(&before),
+                switches: client.take_switches(),
+                reasoning: client.take_reasoning(),
+                transcript: client.take_transcript(),
+                members: client
+                    .member_stats()
+                    .into_iter()
+                    .zip(members_before)
+                    .map(|((name, stats), (_, before))| (name, stats.since(&before)))
+                    .collect(),
+            }
+        });
+        task.await.unwrap_or_else(|e| {
+            Outcome::failed(ClientError::Other(format!("Request panicked: {}", e)))
+        })
+    }
+}
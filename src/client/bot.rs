@@ -0,0 +1,25 @@
+use super::{
+    error::ClientError,
+    few_shot,
+    models::{BoardView, InputContent, OutputContent},
+    ApiClient,
+};
+
+/// Plans the shortest path to the food with the BFS bot, works without any server
+#[derive(Default)]
+pub struct BotClient {
+    board: BoardView,
+}
+
+impl ApiClient for BotClient {
+    fn snake_commands(&mut self, input: InputContent) -> Result<OutputContent, ClientError> {
+        few_shot::bfs_plan(&input, &self.board)
+            .filter(|commands| !commands.is_empty())
+            .map(|commands| OutputContent { commands })
+            .ok_or_else(|| ClientError::Other("The bot found no path to the food".to_string()))
+    }
+
+    fn observe_board(&mut self, board: &BoardView) {
+        self.board = board.clone();
+    }
+}
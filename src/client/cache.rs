@@ -0,0 +1,331 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use log::*;
+use serde_derive::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    correction::CorrectionStats,
+    error::ClientError,
+    few_shot::PlanStats,
+    models::{BoardView, InputContent, OutputContent},
+    transcript::Transcript,
+    usage::Usage,
+    ApiClient,
+};
+
+/// cells around the head that are part of the key in each direction
+const NEARBY: i32 = 3;
+
+/// how often the cache answered instead of the model
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: u32,
+    pub misses: u32,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.hits + self.misses;
+        let percent = if total == 0 {
+            0.0
+        } else {
+            100.0 * self.hits as f64 / total as f64
+        };
+        write!(
+            f,
+            "{} cache hits, {} misses ({:.0}%)",
+            self.hits, self.misses, percent
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedAnswer {
+    key: String,
+    output: OutputContent,
+}
+
+/// Answers states that were already asked with the same model and prompt from the disk,
+/// the other states are passed to the client and their answers are saved
+pub struct CachedClient {
+    client: Box<dyn ApiClient>,
+    /// provider, model and a hash of the prompt settings of the client,
+    /// the answers of other profiles are never used
+    profile: String,
+    answers: HashMap<String, OutputContent>,
+    path: Option<PathBuf>,
+    board: BoardView,
+    stats: CacheStats,
+}
+
+impl CachedClient {
+    pub fn new(client: Box<dyn ApiClient>, profile: String, path: Option<PathBuf>) -> Self {
+        let answers = match &path {
+            Some(path) if path.exists() => load(path, &profile),
+            _ => HashMap::new(),
+        };
+        Self {
+            client,
+            profile,
+            answers,
+            path,
+            board: BoardView::default(),
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// The state around the head: the food relative to the head, the heading and the cells
+    /// next to the head taken by a snake or outside the board, so the same situation
+    /// in another part of the board is answered from the cache too
+    fn key(&self, input: &InputContent) -> String {
+        let (x, y) = (input.snake_head_x, input.snake_head_y);
+        let board = &self.board;
+        let blocked = |cx: i32, cy: i32| {
+            let outside =
+                board.width > 0 && (cx < 0 || cy < 0 || cx >= board.width || cy >= board.height);
+            outside || board.occupied.iter().any(|p| (p.x, p.y) == (cx, cy))
+        };
+        let nearby: Vec<(i32, i32)> = (-NEARBY..=NEARBY)
+            .flat_map(|dy| (-NEARBY..=NEARBY).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| (dx, dy) != (0, 0) && blocked(x + dx, y + dy))
+            .collect();
+        let state = serde_json::json!({
+            "food": (input.food_x - x, input.food_y - y),
+            "heading": board.heading,
+            "nearby": nearby,
+        });
+        format!("{}|{}", self.profile, state)
+    }
+
+    fn save(&mut self, key: String, output: &OutputContent) {
+        if let Some(path) = &self.path {
+            let answer = CachedAnswer {
+                key: key.clone(),
+                output: output.clone(),
+            };
+            if let Err(e) = append(path, &answer) {
+                error!("Failed to save the answer to the cache: {}", e);
+            }
+        }
+        self.answers.insert(key, output.clone());
+    }
+}
+
+impl ApiClient for CachedClient {
+    fn snake_commands(&mut self, input: InputContent) -> Result<OutputContent, ClientError> {
+        self.stream_snake_commands(input, &mut |_| {})
+    }
+
+    fn observe_board(&mut self, board: &BoardView) {
+        self.board = board.clone();
+        self.client.observe_board(board);
+    }
+
+    fn set_cancellation(&mut self, token: CancellationToken) {
+        self.client.set_cancellation(token);
+    }
+
+    fn correction_stats(&self) -> CorrectionStats {
+        self.client.correction_stats()
+    }
+
+    fn plan_stats(&self) -> PlanStats {
+        self.client.plan_stats()
+    }
+
+    fn cache_stats(&self) -> CacheStats {
+        self.stats.clone()
+    }
+
+    fn usage(&self) -> Usage {
+        self.client.usage()
+    }
+
+    fn take_reasoning(&mut self) -> Option<String> {
+        self.client.take_reasoning()
+    }
+
+    fn take_transcript(&mut self) -> Option<Transcript> {
+        self.client.take_transcript()
+    }
+
+    fn stream_snake_commands(
+        &mut self,
+        input: InputContent,
+        on_commands: &mut dyn FnMut(OutputContent),
+    ) -> Result<OutputContent, ClientError> {
+        let key = self.key(&input);
+        if let Some(output) = self.answers.get(&key) {
+            self.stats.hits += 1;
+            debug!("Answered from the cache: {}", self.stats);
+            on_commands(output.clone());
+            return Ok(output.clone());
+        }
+        self.stats.misses += 1;
+        let output = self.client.stream_snake_commands(input, on_commands)?;
+        self.save(key, &output);
+        Ok(output)
+    }
+}
+
+/// cache file in the user's data dir, e.g. ~/.local/share/snake-llm/cache.jsonl
+pub fn default_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("snake-llm").join("cache.jsonl"))
+}
+
+/// FNV-1a hash of the text, unlike the std hasher it stays the same across builds
+/// so the keys saved in the file keep matching
+pub fn fingerprint(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// answers of the profile, the file is shared by all profiles
+fn load(path: &Path, profile: &str) -> HashMap<String, OutputContent> {
+    let prefix = format!("{}|", profile);
+    match fs::read_to_string(path) {
+        Ok(text) => text
+            .lines()
+            .filter_map(|line| serde_json::from_str::<CachedAnswer>(line).ok())
+            .filter(|answer| answer.key.starts_with(&prefix))
+            .map(|answer| (answer.key, answer.output))
+            .collect(),
+        Err(e) => {
+            error!("Failed to read the cache {}: {}", path.display(), e);
+            HashMap::new()
+        }
+    }
+}
+
+fn append(path: &Path, answer: &CachedAnswer) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let line = serde_json::to_string(answer).map_err(|e| e.to_string())?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| e.to_string())?;
+    writeln!(file, "{}", line).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::models::Commands;
+    use crate::models::{Direction, Point};
+
+    /// moves one more step up on every call
+    struct Counter(u32);
+
+    impl ApiClient for Counter {
+        fn snake_commands(&mut self, _input: InputContent) -> Result<OutputContent, ClientError> {
+            self.0 += 1;
+            Ok(OutputContent {
+                commands: vec![Commands {
+                    command: Direction::Up,
+                    repeat: self.0 as i32,
+                }],
+            })
+        }
+    }
+
+    fn input(head_x: i32) -> InputContent {
+        InputContent {
+            snake_head_x: head_x,
+            snake_head_y: 5,
+            food_x: 9,
+            food_y: 9,
+        }
+    }
+
+    #[test]
+    fn test_same_state_is_answered_from_the_file() {
+        let path =
+            std::env::temp_dir().join(format!("snake-llm-cache-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let board = BoardView {
+            width: 10,
+            height: 10,
+            occupied: vec![Point::new(5, 5), Point::new(5, 4)],
+            snake: Vec::new(),
+            heading: Some(Direction::Up),
+        };
+
+        let mut client = CachedClient::new(
+            Box::new(Counter(0)),
+            "ollama|phi3|default".to_string(),
+            Some(path.clone()),
+        );
+        client.observe_board(&board);
+        let first = client.snake_commands(input(5)).unwrap();
+        let again = client.snake_commands(input(5)).unwrap();
+        let other = client.snake_commands(input(6)).unwrap();
+
+        assert_eq!(first, again);
+        assert_ne!(first, other);
+        assert_eq!(client.cache_stats(), CacheStats { hits: 1, misses: 2 });
+
+        // a new client reads the answers of the profile from the file
+        let mut reloaded = CachedClient::new(
+            Box::new(Counter(10)),
+            "ollama|phi3|default".to_string(),
+            Some(path.clone()),
+        );
+        reloaded.observe_board(&board);
+        assert_eq!(reloaded.snake_commands(input(5)).unwrap(), first);
+        let mut other_model = CachedClient::new(
+            Box::new(Counter(10)),
+            "ollama|llama3|default".to_string(),
+            Some(path.clone()),
+        );
+        other_model.observe_board(&board);
+        assert_ne!(other_model.snake_commands(input(5)).unwrap(), first);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_equal_states_share_the_key() {
+        let client = |board: &BoardView| {
+            let mut client = CachedClient::new(
+                Box::new(Counter(0)),
+                "ollama|phi3|default".to_string(),
+                None,
+            );
+            client.observe_board(board);
+            client
+        };
+        let board = |head: Point, tail: Point| BoardView {
+            width: 60,
+            height: 40,
+            occupied: vec![head, tail],
+            snake: Vec::new(),
+            heading: Some(Direction::Up),
+        };
+        let input = |head_x: i32, food_x: i32| InputContent {
+            snake_head_x: head_x,
+            snake_head_y: 20,
+            food_x,
+            food_y: 25,
+        };
+
+        let here = client(&board(Point::new(10, 20), Point::new(10, 19))).key(&input(10, 12));
+        let moved = client(&board(Point::new(30, 20), Point::new(30, 19))).key(&input(30, 32));
+        let other_food = client(&board(Point::new(30, 20), Point::new(30, 19))).key(&input(30, 33));
+        let near_wall = client(&board(Point::new(1, 20), Point::new(1, 19))).key(&input(1, 3));
+
+        assert_eq!(here, moved);
+        assert_ne!(here, other_food);
+        assert_ne!(here, near_wall);
+    }
+}
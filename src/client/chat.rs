@@ -0,0 +1,362 @@
+use std::time::{Duration, Instant};
+
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    correction::{self, CorrectionStats},
+    error::ClientError,
+    few_shot::{ExamplePicker, PlanStats},
+    http::{self, BodyReader},
+    memory::{self, Conversation, HistoryPolicy},
+    models::{BoardView, InputContent, Message, OutputContent, PromptVariant, Role, SYSTEM_PROMPT},
+    prompt::PromptTemplate,
+    reasoning, repair,
+    stream::{CommandStreamParser, Streamed},
+    transcript::Transcript,
+    usage::{Tokens, Usage},
+    ApiClient,
+};
+
+/// Request and response format of the chat API of a provider,
+/// the ChatClient keeps the conversation and reads the answers
+pub trait ChatApi: Send {
+    /// context size of the models without it in the name
+    const CONTEXT_TOKENS: usize;
+
+    /// the context size is guessed from the name of the model
+    fn model(&self) -> &str;
+
+    fn set_temperature(&mut self, temperature: f32);
+
+    fn set_timeout(&mut self, timeout: Duration);
+
+    /// asks for an answer matching the schema of OutputContent
+    fn set_structured_output(&mut self, enabled: bool);
+
+    /// request sending the messages, the system prompt first
+    fn request(
+        &self,
+        client: &Client,
+        messages: &[Message],
+        stream: bool,
+    ) -> Result<RequestBuilder, String>;
+
+    /// text of the answer in the response body with the tokens reported by the server
+    fn read_response(&self, body: &str) -> Result<(String, Option<Tokens>), String>;
+
+    /// reads a streamed answer, passes every piece of the text to `on_chunk`
+    fn read_stream(
+        &self,
+        body: BodyReader,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<Streamed, ClientError>;
+
+    /// Stops sending the structured output fields when the `error` of a rejected request
+    /// is about them, false when it's about something else or there is nothing to drop
+    fn drop_structured_output(&mut self, _error: &str) -> bool {
+        false
+    }
+}
+
+/// Conversation with a model through the chat API of its provider.
+/// Builds the messages from the template, the history and the few-shot examples,
+/// asks for corrections and keeps the usage, the reasoning and the transcript of the answers
+pub struct ChatClient<A: ChatApi> {
+    api: A,
+    client: Client,
+    cancel: CancellationToken,
+    board: BoardView,
+    stream: bool,
+    max_corrections: u32,
+    corrections: CorrectionStats,
+    plans: PlanStats,
+    usage: Usage,
+    /// reasoning written before the last answer
+    reasoning: Option<String>,
+    transcript: Transcript,
+    few_shot: Option<ExamplePicker>,
+    conversation: Conversation,
+    template: PromptTemplate,
+    /// messages of the current request, the system prompt first
+    messages: Vec<Message>,
+}
+
+impl<A: ChatApi> ApiClient for ChatClient<A> {
+    fn snake_commands(&mut self, input: InputContent) -> Result<OutputContent, ClientError> {
+        self.snake_commands(input)
+    }
+
+    fn observe_board(&mut self, board: &BoardView) {
+        self.board = board.clone();
+    }
+
+    fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancel = token;
+    }
+
+    fn correction_stats(&self) -> CorrectionStats {
+        self.corrections.clone()
+    }
+
+    fn plan_stats(&self) -> PlanStats {
+        self.plans.clone()
+    }
+
+    fn usage(&self) -> Usage {
+        self.usage.clone()
+    }
+
+    fn take_reasoning(&mut self) -> Option<String> {
+        self.reasoning.take()
+    }
+
+    fn take_transcript(&mut self) -> Option<Transcript> {
+        Some(std::mem::take(&mut self.transcript))
+    }
+
+    fn stream_snake_commands(
+        &mut self,
+        input: InputContent,
+        on_commands: &mut dyn FnMut(OutputContent),
+    ) -> Result<OutputContent, ClientError> {
+        if self.stream {
+            return self.streamed_commands(input, on_commands);
+        }
+        let output = self.snake_commands(input)?;
+        on_commands(output.clone());
+        Ok(output)
+    }
+}
+
+impl<A: ChatApi> ChatClient<A> {
+    pub fn new(api: A) -> Self {
+        let context_tokens = memory::context_limit(api.model(), A::CONTEXT_TOKENS);
+        Self {
+            api,
+            client: Client::new(),
+            cancel: CancellationToken::new(),
+            board: BoardView::default(),
+            stream: false,
+            max_corrections: 0,
+            corrections: CorrectionStats::default(),
+            plans: PlanStats::default(),
+            usage: Usage::default(),
+            reasoning: None,
+            transcript: Transcript::default(),
+            few_shot: None,
+            template: PromptTemplate::builtin(&PromptVariant::Default),
+            conversation: Conversation::new(SYSTEM_PROMPT, context_tokens),
+            messages: Vec::new(),
+        }
+    }
+
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.api.set_temperature(temperature);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.api.set_timeout(timeout);
+        self
+    }
+
+    pub fn with_template(mut self, template: PromptTemplate) -> Self {
+        self.conversation.set_system_prompt(&template.system);
+        self.conversation.set_examples(template.examples.clone());
+        self.template = template;
+        self
+    }
+
+    /// examples similar to the current state are sent before every request
+    pub fn with_few_shot(mut self, picker: ExamplePicker) -> Self {
+        self.few_shot = Some(picker);
+        self
+    }
+
+    pub fn with_stream(mut self, stream: bool) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    pub fn with_history(mut self, policy: HistoryPolicy) -> Self {
+        self.conversation.set_policy(policy);
+        self
+    }
+
+    /// context size of the model, older turns are dropped to keep the request under it
+    pub fn with_context_tokens(mut self, tokens: usize) -> Self {
+        self.conversation.set_context_tokens(tokens);
+        self
+    }
+
+    /// Answers that can't be parsed or run into a wall or a snake are sent back to the model
+    /// with the problem, up to `max` times per request. Not used when streaming
+    pub fn with_max_corrections(mut self, max: u32) -> Self {
+        self.max_corrections = max;
+        self
+    }
+
+    pub fn with_structured_output(mut self, enabled: bool) -> Self {
+        self.api.set_structured_output(enabled);
+        self
+    }
+
+    pub fn snake_commands(&mut self, input: InputContent) -> Result<OutputContent, ClientError> {
+        if self.stream {
+            return self.streamed_commands(input, &mut |_| {});
+        }
+        let user = self.add_message_to_request(&input);
+
+        let answer = self.answer()?;
+        let board = self.board.clone();
+        let mut corrections = CorrectionStats::default();
+        let reviewed = correction::correct(
+            answer,
+            self.max_corrections,
+            &input,
+            &board,
+            &mut corrections,
+            |answer, problem| {
+                self.transcript.correction(problem);
+                self.add_correction_to_request(answer, problem);
+                self.answer()
+            },
+        );
+        self.corrections.add(&corrections);
+        let (output, answer) = reviewed?;
+        self.learn(&input, &output);
+        self.conversation.record(user, answer);
+        Ok(output)
+    }
+
+    /// answer of the model to the current request without the reasoning
+    fn answer(&mut self) -> Result<String, ClientError> {
+        let sent_at = Instant::now();
+        let resp = self.send_request()?;
+        let body = http::text(resp, &self.cancel)?;
+
+        let (content, tokens) = self.api.read_response(&body)?;
+        let tokens = tokens.unwrap_or_else(|| Tokens::estimate(&self.messages, &content));
+        self.usage.record(tokens, sent_at.elapsed());
+        Ok(self.split_reasoning(&content))
+    }
+
+    fn streamed_commands(
+        &mut self,
+        input: InputContent,
+        on_commands: &mut dyn FnMut(OutputContent),
+    ) -> Result<OutputContent, ClientError> {
+        let user = self.add_message_to_request(&input);
+
+        let sent_at = Instant::now();
+        let resp = self.send_request()?;
+
+        let mut parser = CommandStreamParser::new(&self.board);
+        let mut emitted = false;
+        let streamed = self
+            .api
+            .read_stream(BodyReader::new(resp, &self.cancel), &mut |piece| {
+                let commands = parser.push(piece);
+                if !commands.is_empty() {
+                    emitted = true;
+                    on_commands(OutputContent { commands });
+                }
+            })?;
+        let tokens = streamed
+            .tokens
+            .unwrap_or_else(|| Tokens::estimate(&self.messages, &streamed.content));
+        self.usage.record(tokens, sent_at.elapsed());
+
+        let answer = self.split_reasoning(&streamed.content);
+        let output = repair::read_output(&answer, &self.board)?;
+        if !emitted {
+            on_commands(output.clone());
+        }
+        self.learn(&input, &output);
+        self.conversation.record(user, answer);
+        Ok(output)
+    }
+
+    /// keeps the reasoning and the transcript of the response, returns the answer
+    fn split_reasoning(&mut self, response: &str) -> String {
+        let (reasoning, answer) = reasoning::split(response);
+        self.reasoning = reasoning;
+        self.transcript
+            .record(&self.messages, response, &answer, &self.board);
+        answer
+    }
+
+    /// builds the messages of the request, returns the user message
+    fn add_message_to_request(&mut self, input: &InputContent) -> String {
+        let user = self.template.render_user(input, &self.board);
+        if let Some(picker) = &mut self.few_shot {
+            let mut examples = self.template.examples.clone();
+            examples.extend(picker.examples(&self.template, input, &self.board));
+            self.conversation.set_examples(examples);
+        }
+        self.transcript = Transcript::default();
+        // the corrections of the previous request are not part of the conversation
+        self.messages = self.conversation.messages(&user);
+        user
+    }
+
+    /// counts the plans reaching the food and keeps them as examples
+    fn learn(&mut self, input: &InputContent, output: &OutputContent) {
+        self.plans.record(input, &self.board, &output.commands);
+        if let Some(picker) = &mut self.few_shot {
+            picker.learn(input, &self.board, &output.commands);
+        }
+    }
+
+    fn add_correction_to_request(&mut self, answer: String, problem: &str) {
+        self.messages.push(Message {
+            role: Role::Assistant.as_string(),
+            content: answer,
+        });
+        self.messages.push(Message {
+            role: Role::User.as_string(),
+            content: correction::correction_message(problem),
+        });
+    }
+
+    /// Sends the request, if the server rejects the structured output fields
+    /// it is sent again without them and the prompt alone asks for JSON from then on
+    fn send_request(&mut self) -> Result<Response, ClientError> {
+        let request = self
+            .api
+            .request(&self.client, &self.messages, self.stream)?;
+        let resp = http::send(request, &self.cancel)?;
+        let status = resp.status();
+
+        if status == StatusCode::OK {
+            return Ok(resp);
+        }
+        if is_unsupported(status) {
+            let error = http::text(resp, &self.cancel).unwrap_or_default();
+            if self.api.drop_structured_output(&error) {
+                return self.send_request();
+            }
+        }
+        Err(ClientError::Status(status))
+    }
+}
+
+/// status of a server that doesn't accept a field of the request
+fn is_unsupported(status: StatusCode) -> bool {
+    status == StatusCode::BAD_REQUEST || status == StatusCode::UNPROCESSABLE_ENTITY
+}
+
+/// true when the error of a rejected request names one of the fields
+pub fn mentions(error: &str, fields: &[&str]) -> bool {
+    let error = error.to_lowercase();
+    fields
+        .iter()
+        .any(|field| error.contains(&field.to_lowercase()))
+}
+
+/// JSON body of a request
+pub fn body(request: &impl Serialize) -> Result<String, String> {
+    serde_json::to_string(request).map_err(|e| format!("Failed to serialize request: {}", e))
+}
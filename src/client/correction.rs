@@ -0,0 +1,324 @@
+use std::fmt;
+
+use log::warn;
+
+use super::{
+    models::{BoardView, Commands, InputContent, OutputContent},
+    repair,
+};
+use crate::models::{Direction, Point};
+use crate::snake::{Crash, Snake, Step};
+
+/// how often the model was asked to correct its answer
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CorrectionStats {
+    /// correction requests sent
+    pub asked: u32,
+    /// answers that were usable after the corrections
+    pub fixed: u32,
+    /// answers that were still wrong when the corrections ran out
+    pub failed: u32,
+}
+
+impl CorrectionStats {
+    pub fn add(&mut self, other: &CorrectionStats) {
+        self.asked += other.asked;
+        self.fixed += other.fixed;
+        self.failed += other.failed;
+    }
+}
+
+impl fmt::Display for CorrectionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} corrections asked, {} answers fixed, {} failed",
+            self.asked, self.fixed, self.failed
+        )
+    }
+}
+
+/// Parses the answer and checks the plan, the error describes the problem to the model
+pub fn review(
+    text: &str,
+    input: &InputContent,
+    board: &BoardView,
+) -> Result<OutputContent, String> {
+    let output = repair::read_output(text, board)?;
+    match check_plan(input, board, &output.commands) {
+        Some(problem) => Err(problem),
+        None => Ok(output),
+    }
+}
+
+/// Reads the answer of the model, with `max` above 0 its plan is checked too.
+/// An unusable answer is passed to `ask_again` with the problem, which asks the model
+/// for a corrected one, until an answer can be used or `max` corrections were asked.
+/// Returns the plan with the answer it was read from
+pub fn correct<E: From<String>>(
+    mut answer: String,
+    max: u32,
+    input: &InputContent,
+    board: &BoardView,
+    stats: &mut CorrectionStats,
+    mut ask_again: impl FnMut(String, &str) -> Result<String, E>,
+) -> Result<(OutputContent, String), E> {
+    let mut corrections = 0;
+    loop {
+        let review = if max > 0 {
+            review(&answer, input, board)
+        } else {
+            repair::read_output(&answer, board)
+        };
+        match review {
+            Ok(output) => {
+                if corrections > 0 {
+                    stats.fixed += 1;
+                }
+                return Ok((output, answer));
+            }
+            Err(problem) if corrections < max => {
+                corrections += 1;
+                stats.asked += 1;
+                warn!("Asking for a correction: {}", problem);
+                answer = ask_again(answer, &problem)?;
+            }
+            Err(e) => {
+                if corrections > 0 {
+                    stats.failed += 1;
+                }
+                return Err(e.into());
+            }
+        }
+    }
+}
+
+/// user message sent after an answer that can't be used
+pub fn correction_message(problem: &str) -> String {
+    format!(
+        "Your answer can't be used: {}. Answer again with the corrected JSON only.",
+        problem
+    )
+}
+
+/// Plays the plan tick by tick like the game does, the body of the snake follows the head
+/// and it grows on the food. Passes every step with its command to `on_step` until it returns false
+pub fn play_plan(
+    input: &InputContent,
+    board: &BoardView,
+    commands: &[Commands],
+    mut on_step: impl FnMut(&Commands, Step) -> bool,
+) {
+    let head = Point::new(input.snake_head_x, input.snake_head_y);
+    let food = Point::new(input.food_x, input.food_y);
+    let cells = if board.snake.is_empty() {
+        vec![head]
+    } else {
+        board.snake.clone()
+    };
+    let others: Vec<Point> = board
+        .occupied
+        .iter()
+        .filter(|p| !cells.contains(p))
+        .cloned()
+        .collect();
+    // without the heading the snake can start in any direction
+    let heading = board
+        .heading
+        .clone()
+        .or_else(|| commands.first().map(|c| c.command.clone()))
+        .unwrap_or(Direction::Up);
+    let mut snake = Snake::from_cells(cells, heading);
+    for c in commands {
+        for _ in 0..c.repeat {
+            let step = snake.step(&c.command, &food, (board.width, board.height), &others);
+            if !on_step(c, step) {
+                return;
+            }
+        }
+    }
+}
+
+/// Plays the plan from the snake head, the problem when it leaves the board or runs into a snake
+pub fn check_plan(
+    input: &InputContent,
+    board: &BoardView,
+    commands: &[Commands],
+) -> Option<String> {
+    if board.width <= 0 || board.height <= 0 {
+        return None;
+    }
+    let mut problem = None;
+    play_plan(input, board, commands, |c, step| {
+        let name = repair::direction_name(&c.command);
+        problem = match step {
+            Step::Crashed(Crash::Wall(p)) if p.x < 0 || p.x >= board.width => Some(format!(
+                "moving {} {} hits the wall at x={}",
+                name, c.repeat, p.x
+            )),
+            Step::Crashed(Crash::Wall(p)) => Some(format!(
+                "moving {} {} hits the wall at y={}",
+                name, c.repeat, p.y
+            )),
+            Step::Crashed(Crash::Snake(p)) => Some(format!(
+                "moving {} {} runs into the snake at {},{}",
+                name, c.repeat, p.x, p.y
+            )),
+            Step::Moved | Step::Ate => None,
+        };
+        problem.is_none()
+    });
+    problem
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[case(r#"{"commands": [{"command": "up", "repeat": 5}]}"#, None)]
+    #[case(
+        r#"{"commands": [{"command": "up", "repeat": 5}, {"command": "right", "repeat": 20}]}"#,
+        Some("moving right 20 hits the wall at x=60")
+    )]
+    #[case(
+        r#"{"commands": [{"command": "down", "repeat": 30}]}"#,
+        Some("moving down 30 hits the wall at y=-1")
+    )]
+    #[case(
+        r#"{"commands": [{"command": "left", "repeat": 4}]}"#,
+        Some("moving left 4 runs into the snake at 49,20")
+    )]
+    fn test_review(#[case] text: &str, #[case] problem: Option<&str>) {
+        let review = review(text, &input(), &board());
+
+        assert_eq!(review.err().as_deref(), problem);
+    }
+
+    #[test]
+    fn test_check_plan_turning_back() {
+        let board = BoardView {
+            heading: Some(Direction::Right),
+            ..board()
+        };
+        let commands = vec![Commands {
+            command: Direction::Left,
+            repeat: 2,
+        }];
+
+        // the game doesn't turn the snake back, it keeps going right
+        let problem = check_plan(&input(), &board, &commands);
+
+        assert_eq!(problem, None);
+    }
+
+    #[rstest]
+    #[case(0, 0, None)]
+    // the snake grows on the food, so its tail is still there
+    #[case(49, 20, Some("moving up 1 runs into the snake at 49,21"))]
+    fn test_check_plan_moves_the_body(
+        #[case] food_x: i32,
+        #[case] food_y: i32,
+        #[case] problem: Option<&str>,
+    ) {
+        // curled up, the head follows the tail to the left and up
+        let snake = vec![
+            Point::new(50, 20),
+            Point::new(50, 21),
+            Point::new(49, 21),
+            Point::new(49, 20),
+        ];
+        let board = BoardView {
+            width: 60,
+            height: 40,
+            occupied: snake.clone(),
+            snake,
+            heading: Some(Direction::Down),
+        };
+        let input = InputContent {
+            food_x,
+            food_y,
+            ..input()
+        };
+        let commands = vec![
+            Commands {
+                command: Direction::Left,
+                repeat: 1,
+            },
+            Commands {
+                command: Direction::Up,
+                repeat: 1,
+            },
+        ];
+
+        let checked = check_plan(&input, &board, &commands);
+
+        assert_eq!(checked.as_deref(), problem);
+    }
+
+    #[rstest]
+    #[case(vec![r#"{"commands": [{"command": "up", "repeat": 5}]}"#], 2, Ok(0), (0, 0, 0))]
+    #[case(
+        vec![r#"{"commands": [{"command": "down", "repeat": 30}]}"#, r#"{"commands": [{"command": "up", "repeat": 5}]}"#],
+        2,
+        Ok(1),
+        (1, 1, 0)
+    )]
+    #[case(
+        vec![r#"{"commands": [{"command": "down", "repeat": 30}]}"#, "no plan", "no plan"],
+        2,
+        Err(()),
+        (2, 0, 1)
+    )]
+    // without corrections the plan is not checked
+    #[case(vec![r#"{"commands": [{"command": "down", "repeat": 30}]}"#], 0, Ok(0), (0, 0, 0))]
+    fn test_correct(
+        #[case] answers: Vec<&str>,
+        #[case] max: u32,
+        #[case] used: Result<usize, ()>,
+        #[case] expected: (u32, u32, u32),
+    ) {
+        let mut stats = CorrectionStats::default();
+        let mut next = 1;
+
+        let result = correct(
+            answers[0].to_string(),
+            max,
+            &input(),
+            &board(),
+            &mut stats,
+            |_, _| {
+                next += 1;
+                Ok(answers[next - 1].to_string())
+            },
+        );
+
+        assert_eq!(
+            result.map(|(_, answer)| answer).map_err(|_: String| ()),
+            used.map(|i| answers[i].to_string())
+        );
+        assert_eq!((stats.asked, stats.fixed, stats.failed), expected);
+    }
+
+    fn input() -> InputContent {
+        InputContent {
+            snake_head_x: 50,
+            snake_head_y: 20,
+            food_x: 0,
+            food_y: 0,
+        }
+    }
+
+    /// the body of the snake is left of the head
+    fn board() -> BoardView {
+        BoardView {
+            width: 60,
+            height: 40,
+            occupied: vec![Point::new(50, 20), Point::new(49, 20), Point::new(48, 20)],
+            snake: Vec::new(),
+            heading: None,
+        }
+    }
+}
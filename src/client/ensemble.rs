@@ -0,0 +1,437 @@
+use std::fmt;
+
+use log::*;
+use serde_derive::Deserialize;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    async_client::AsyncClient,
+    correction,
+    error::ClientError,
+    few_shot::PlanStats,
+    http,
+    models::{BoardView, InputContent, OutputContent},
+    transcript::Transcript,
+    usage::Usage,
+    ApiClient,
+};
+use crate::models::Direction;
+
+/// how the plans of the members are combined
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// the first move most members agree on, waits for all answers
+    #[default]
+    Majority,
+    /// the first member in the list with a plan that doesn't hit a wall or a snake
+    FirstSafe,
+    /// the first answer that arrives with a plan
+    Fastest,
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Strategy::Majority => "majority",
+            Strategy::FirstSafe => "first safe",
+            Strategy::Fastest => "fastest",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// how often a member agreed with the chosen plan
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemberStats {
+    /// answers with a plan
+    pub answers: u32,
+    /// answers starting with the chosen move
+    pub agreed: u32,
+    /// requests that ended with an error
+    pub failed: u32,
+}
+
+impl MemberStats {
+    pub fn add(&mut self, other: &MemberStats) {
+        self.answers += other.answers;
+        self.agreed += other.agreed;
+        self.failed += other.failed;
+    }
+
+    /// answers given since the `earlier` snapshot of the same member
+    pub fn since(&self, earlier: &MemberStats) -> MemberStats {
+        MemberStats {
+            answers: self.answers.saturating_sub(earlier.answers),
+            agreed: self.agreed.saturating_sub(earlier.agreed),
+            failed: self.failed.saturating_sub(earlier.failed),
+        }
+    }
+}
+
+impl fmt::Display for MemberStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = if self.answers == 0 {
+            0.0
+        } else {
+            100.0 * self.agreed as f64 / self.answers as f64
+        };
+        write!(
+            f,
+            "{} of {} answers agree ({:.0}%), {} failed",
+            self.agreed, self.answers, percent, self.failed
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Decision {
+    /// more answers are needed
+    Wait,
+    Pick(usize),
+    /// all members answered, none of the plans can be used
+    NoPlan,
+}
+
+/// Asks all members about the same state at the same time and combines their plans
+pub struct Ensemble {
+    strategy: Strategy,
+    members: Vec<(String, AsyncClient)>,
+    stats: Vec<MemberStats>,
+    board: BoardView,
+    cancel: CancellationToken,
+    plans: PlanStats,
+    usage: Usage,
+    /// reasoning of the member that was followed
+    reasoning: Option<String>,
+    transcript: Option<Transcript>,
+}
+
+impl Ensemble {
+    pub fn new(strategy: Strategy, members: Vec<(String, AsyncClient)>) -> Self {
+        Self {
+            strategy,
+            stats: vec![MemberStats::default(); members.len()],
+            members,
+            board: BoardView::default(),
+            cancel: CancellationToken::new(),
+            plans: PlanStats::default(),
+            usage: Usage::default(),
+            reasoning: None,
+            transcript: None,
+        }
+    }
+
+    async fn vote(&mut self, input: InputContent) -> Result<OutputContent, ClientError> {
+        // cancels the members that are still thinking once the plan is chosen
+        let undecided = self.cancel.child_token();
+        let mut tasks = JoinSet::new();
+        for (i, (_, client)) in self.members.iter().enumerate() {
+            let (client, input, board) = (client.clone(), input.clone(), self.board.clone());
+            let cancel = undecided.clone();
+            tasks.spawn(async move {
+                (
+                    i,
+                    client
+                        .stream_snake_commands(input, board, cancel, |_| {})
+                        .await,
+                )
+            });
+        }
+
+        let mut answers: Vec<Option<Result<OutputContent, ClientError>>> =
+            vec![None; self.members.len()];
+        let mut reasonings = vec![None; self.members.len()];
+        let mut transcripts = vec![None; self.members.len()];
+        let mut decision = Decision::Wait;
+        while let Some(joined) = tasks.join_next().await {
+            let Ok((i, outcome)) = joined else {
+                continue;
+            };
+            self.usage.add(&outcome.usage);
+            if decision != Decision::Wait {
+                continue;
+            }
+            answers[i] = Some(outcome.result);
+            reasonings[i] = outcome.reasoning;
+            transcripts[i] = outcome.transcript;
+            decision = decide(&self.strategy, &answers, i, &input, &self.board);
+            if decision != Decision::Wait {
+                undecided.cancel();
+            }
+        }
+
+        if self.cancel.is_cancelled() {
+            return Err(ClientError::Cancelled);
+        }
+        match decision {
+            Decision::Pick(i) => {
+                let output = answers[i].clone().unwrap().unwrap();
+                self.record(&answers, &output);
+                info!(
+                    "Ensemble ({}) follows {}: {}",
+                    self.strategy,
+                    self.members[i].0,
+                    self.votes(&answers)
+                );
+                self.plans.record(&input, &self.board, &output.commands);
+                self.reasoning = reasonings[i]
+                    .take()
+                    .map(|text| format!("{}: {}", self.members[i].0, text));
+                self.transcript = transcripts[i].take();
+                Ok(output)
+            }
+            _ => {
+                self.record(&answers, &OutputContent { commands: vec![] });
+                Err(ClientError::Other(format!(
+                    "No member of the ensemble has a plan: {}",
+                    self.votes(&answers)
+                )))
+            }
+        }
+    }
+
+    fn record(
+        &mut self,
+        answers: &[Option<Result<OutputContent, ClientError>>],
+        chosen: &OutputContent,
+    ) {
+        let chosen = first_move(chosen);
+        for (stats, answer) in self.stats.iter_mut().zip(answers) {
+            match answer {
+                Some(Ok(output)) if first_move(output).is_some() => {
+                    stats.answers += 1;
+                    if first_move(output) == chosen {
+                        stats.agreed += 1;
+                    }
+                }
+                Some(Err(e)) if *e != ClientError::Cancelled => stats.failed += 1,
+                _ => (),
+            }
+        }
+        for ((name, _), stats) in self.members.iter().zip(&self.stats) {
+            debug!("{}: {}", name, stats);
+        }
+    }
+
+    /// e.g. "phi3: up, llama3: left, mixtral: error"
+    fn votes(&self, answers: &[Option<Result<OutputContent, ClientError>>]) -> String {
+        self.members
+            .iter()
+            .zip(answers)
+            .map(|((name, _), answer)| {
+                let vote = match answer {
+                    Some(Ok(output)) => match first_move(output) {
+                        Some(direction) => format!("{:?}", direction).to_lowercase(),
+                        None => "no plan".to_string(),
+                    },
+                    Some(Err(_)) => "error".to_string(),
+                    None => "-".to_string(),
+                };
+                format!("{}: {}", name, vote)
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl ApiClient for Ensemble {
+    fn snake_commands(&mut self, input: InputContent) -> Result<OutputContent, ClientError> {
+        http::runtime().block_on(self.vote(input))
+    }
+
+    fn observe_board(&mut self, board: &BoardView) {
+        self.board = board.clone();
+    }
+
+    fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancel = token;
+    }
+
+    fn plan_stats(&self) -> PlanStats {
+        self.plans.clone()
+    }
+
+    fn usage(&self) -> Usage {
+        self.usage.clone()
+    }
+
+    fn take_reasoning(&mut self) -> Option<String> {
+        self.reasoning.take()
+    }
+
+    fn take_transcript(&mut self) -> Option<Transcript> {
+        self.transcript.take()
+    }
+
+    fn member_stats(&self) -> Vec<(String, MemberStats)> {
+        self.members
+            .iter()
+            .map(|(name, _)| name.clone())
+            .zip(self.stats.iter().cloned())
+            .collect()
+    }
+}
+
+fn first_move(output: &OutputContent) -> Option<Direction> {
+    output
+        .commands
+        .iter()
+        .find(|c| c.repeat > 0)
+        .map(|c| c.command.clone())
+}
+
+/// the answer when it has a plan, with its first move
+fn plan(
+    answer: &Option<Result<OutputContent, ClientError>>,
+) -> Option<(&OutputContent, Direction)> {
+    match answer {
+        Some(Ok(output)) => first_move(output).map(|direction| (output, direction)),
+        _ => None,
+    }
+}
+
+/// `last` is the member whose answer just arrived
+fn decide(
+    strategy: &Strategy,
+    answers: &[Option<Result<OutputContent, ClientError>>],
+    last: usize,
+    input: &InputContent,
+    board: &BoardView,
+) -> Decision {
+    let all_answered = answers.iter().all(|a| a.is_some());
+
+    match strategy {
+        Strategy::Majority => {
+            if !all_answered {
+                return Decision::Wait;
+            }
+            let moves: Vec<Option<Direction>> =
+                answers.iter().map(|a| plan(a).map(|(_, d)| d)).collect();
+            let mut best: Option<(usize, usize)> = None;
+            for (i, direction) in moves.iter().enumerate() {
+                let Some(direction) = direction else {
+                    continue;
+                };
+                let votes = moves
+                    .iter()
+                    .filter(|m| m.as_ref() == Some(direction))
+                    .count();
+                if best.is_none_or(|(_, most)| votes > most) {
+                    best = Some((i, votes));
+                }
+            }
+            match best {
+                Some((i, _)) => Decision::Pick(i),
+                None => Decision::NoPlan,
+            }
+        }
+        Strategy::FirstSafe => {
+            for (i, answer) in answers.iter().enumerate() {
+                match (answer, plan(answer)) {
+                    (None, _) => return Decision::Wait,
+                    (_, Some((output, _)))
+                        if correction::check_plan(input, board, &output.commands).is_none() =>
+                    {
+                        return Decision::Pick(i)
+                    }
+                    _ => (),
+                }
+            }
+            Decision::NoPlan
+        }
+        Strategy::Fastest => {
+            if plan(&answers[last]).is_some() {
+                Decision::Pick(last)
+            } else if all_answered {
+                Decision::NoPlan
+            } else {
+                Decision::Wait
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+    use crate::client::models::Commands;
+    use crate::models::Point;
+
+    fn plan(direction: Direction) -> Option<Result<OutputContent, ClientError>> {
+        Some(Ok(OutputContent {
+            commands: vec![Commands {
+                command: direction,
+                repeat: 3,
+            }],
+        }))
+    }
+
+    fn error() -> Option<Result<OutputContent, ClientError>> {
+        Some(Err(ClientError::Network("Request failed".to_string())))
+    }
+
+    #[rstest]
+    #[case(Strategy::Majority, vec![plan(Direction::Left), plan(Direction::Up), plan(Direction::Up)], 2, Decision::Pick(1))]
+    #[case(Strategy::Majority, vec![plan(Direction::Left), plan(Direction::Up), None], 1, Decision::Wait)]
+    // a tie goes to the member listed first
+    #[case(Strategy::Majority, vec![error(), plan(Direction::Down), plan(Direction::Up)], 2, Decision::Pick(1))]
+    #[case(Strategy::Majority, vec![error(), error()], 1, Decision::NoPlan)]
+    // the plan of the first member runs into the snake
+    #[case(Strategy::FirstSafe, vec![plan(Direction::Right), plan(Direction::Up)], 1, Decision::Pick(1))]
+    #[case(Strategy::FirstSafe, vec![None, plan(Direction::Up)], 1, Decision::Wait)]
+    #[case(Strategy::Fastest, vec![None, plan(Direction::Right)], 1, Decision::Pick(1))]
+    #[case(Strategy::Fastest, vec![None, error()], 1, Decision::Wait)]
+    #[case(Strategy::Fastest, vec![error(), error()], 0, Decision::NoPlan)]
+    fn test_decide(
+        #[case] strategy: Strategy,
+        #[case] answers: Vec<Option<Result<OutputContent, ClientError>>>,
+        #[case] last: usize,
+        #[case] expected: Decision,
+    ) {
+        let input = InputContent {
+            snake_head_x: 5,
+            snake_head_y: 5,
+            food_x: 5,
+            food_y: 9,
+        };
+        let board = BoardView {
+            width: 10,
+            height: 10,
+            occupied: vec![Point::new(7, 5)],
+            snake: Vec::new(),
+            heading: Some(Direction::Up),
+        };
+
+        assert_eq!(decide(&strategy, &answers, last, &input, &board), expected);
+    }
+
+    #[test]
+    fn test_member_stats_since() {
+        let earlier = MemberStats {
+            answers: 3,
+            agreed: 2,
+            failed: 1,
+        };
+        let mut later = earlier.clone();
+        later.add(&MemberStats {
+            answers: 1,
+            agreed: 1,
+            failed: 0,
+        });
+
+        let delta = later.since(&earlier);
+
+        assert_eq!(
+            delta,
+            MemberStats {
+                answers: 1,
+                agreed: 1,
+                failed: 0,
+            }
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use std::{fmt, io};
+
+use reqwest::StatusCode;
+
+use super::http::CANCELLED;
+
+/// Error of a client, fallback chains switch on its kind
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientError {
+    /// the server answered with an error status
+    Status(StatusCode),
+    /// the server can't be reached or the connection broke
+    Network(String),
+    /// the request was stopped by its cancellation token
+    Cancelled,
+    /// the answer has no usable plan, or the request can't be built
+    Other(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Status(status) => write!(f, "Request failed with status: {}", status),
+            ClientError::Network(message) | ClientError::Other(message) => {
+                write!(f, "{}", message)
+            }
+            ClientError::Cancelled => write!(f, "{}", CANCELLED),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<String> for ClientError {
+    fn from(message: String) -> Self {
+        ClientError::Other(message)
+    }
+}
+
+/// error of reading a streamed body, it keeps the client error the body reader failed with
+impl From<io::Error> for ClientError {
+    fn from(error: io::Error) -> Self {
+        match error
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<ClientError>())
+        {
+            Some(error) => error.clone(),
+            None => ClientError::Network(format!("Failed to read stream: {}", error)),
+        }
+    }
+}
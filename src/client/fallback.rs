@@ -0,0 +1,363 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use log::*;
+use reqwest::StatusCode;
+use serde_derive::Deserialize;
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    error::ClientError,
+    few_shot::PlanStats,
+    http,
+    models::{BoardView, InputContent, OutputContent},
+    transcript::Transcript,
+    usage::Usage,
+    ApiClient,
+};
+
+/// chain member planned by the BFS bot instead of a profile
+pub const BOT: &str = "bot";
+
+/// errors a fallback chain can switch on
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// status 429
+    RateLimit,
+    /// any other error status, e.g. 503 or 401
+    Server,
+    /// the server can't be reached or the connection broke
+    Network,
+    /// no answer within the timeout of the chain
+    Timeout,
+    /// the answer has no usable plan
+    BadAnswer,
+}
+
+impl ErrorKind {
+    /// kind of an error returned by a client
+    pub fn of(error: &ClientError) -> Self {
+        match error {
+            ClientError::Status(StatusCode::TOO_MANY_REQUESTS) => ErrorKind::RateLimit,
+            ClientError::Status(_) => ErrorKind::Server,
+            ClientError::Network(_) => ErrorKind::Network,
+            ClientError::Cancelled | ClientError::Other(_) => ErrorKind::BadAnswer,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ErrorKind::RateLimit => "rate limited",
+            ErrorKind::Server => "server error",
+            ErrorKind::Network => "network error",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::BadAnswer => "bad answer",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// a chain switches on everything but bad answers by default
+pub fn default_switch_on() -> Vec<ErrorKind> {
+    vec![
+        ErrorKind::RateLimit,
+        ErrorKind::Server,
+        ErrorKind::Network,
+        ErrorKind::Timeout,
+    ]
+}
+
+/// change of the provider that plays
+#[derive(Debug, Clone, PartialEq)]
+pub struct Switch {
+    pub from: String,
+    pub to: String,
+    pub reason: String,
+}
+
+impl fmt::Display for Switch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {}: {}", self.from, self.to, self.reason)
+    }
+}
+
+/// Asks the active client of the chain, the next one takes over when it fails.
+/// A client that fails after it streamed some commands answers with them, the next one
+/// takes over from the next request. The first client is tried again after `retry_after`
+pub struct Fallback {
+    chain: Vec<(String, Box<dyn ApiClient>)>,
+    switch_on: Vec<ErrorKind>,
+    timeout: Option<Duration>,
+    retry_after: Duration,
+    active: usize,
+    switched_at: Instant,
+    switches: Vec<Switch>,
+    board: BoardView,
+    cancel: CancellationToken,
+}
+
+impl Fallback {
+    pub fn new(chain: Vec<(String, Box<dyn ApiClient>)>, switch_on: Vec<ErrorKind>) -> Self {
+        Self {
+            chain,
+            switch_on,
+            timeout: None,
+            retry_after: Duration::from_secs(60),
+            active: 0,
+            switched_at: Instant::now(),
+            switches: Vec::new(),
+            board: BoardView::default(),
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// a client that doesn't answer in time counts as failed with a timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    fn switch(&mut self, to: usize, reason: String) {
+        let switch = Switch {
+            from: self.chain[self.active].0.clone(),
+            to: self.chain[to].0.clone(),
+            reason,
+        };
+        info!("Switching provider {}", switch);
+        self.switches.push(switch);
+        self.active = to;
+        self.switched_at = Instant::now();
+    }
+}
+
+impl ApiClient for Fallback {
+    fn snake_commands(&mut self, input: InputContent) -> Result<OutputContent, ClientError> {
+        self.stream_snake_commands(input, &mut |_| {})
+    }
+
+    fn observe_board(&mut self, board: &BoardView) {
+        self.board = board.clone();
+    }
+
+    fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancel = token;
+    }
+
+    fn plan_stats(&self) -> PlanStats {
+        let mut plans = PlanStats::default();
+        for (_, client) in &self.chain {
+            let stats = client.plan_stats();
+            plans.answers += stats.answers;
+            plans.on_target += stats.on_target;
+        }
+        plans
+    }
+
+    fn usage(&self) -> Usage {
+        let mut usage = Usage::default();
+        for (_, client) in &self.chain {
+            usage.add(&client.usage());
+        }
+        usage
+    }
+
+    fn take_switches(&mut self) -> Vec<Switch> {
+        std::mem::take(&mut self.switches)
+    }
+
+    fn take_reasoning(&mut self) -> Option<String> {
+        self.chain[self.active].1.take_reasoning()
+    }
+
+    fn take_transcript(&mut self) -> Option<Transcript> {
+        self.chain[self.active].1.take_transcript()
+    }
+
+    fn stream_snake_commands(
+        &mut self,
+        input: InputContent,
+        on_commands: &mut dyn FnMut(OutputContent),
+    ) -> Result<OutputContent, ClientError> {
+        if self.active > 0 && self.switched_at.elapsed() >= self.retry_after {
+            self.switch(0, "retrying the first provider".to_string());
+        }
+        loop {
+            let (board, active, last) = (self.board.clone(), self.active, self.chain.len() - 1);
+            let (name, client) = &mut self.chain[active];
+            let deadline = self.cancel.child_token();
+            let timer = self.timeout.map(|timeout| {
+                let deadline = deadline.clone();
+                http::runtime().spawn(async move {
+                    tokio::time::sleep(timeout).await;
+                    deadline.cancel();
+                })
+            });
+            client.observe_board(&board);
+            client.set_cancellation(deadline.clone());
+            let mut streamed = Vec::new();
+            let result = client.stream_snake_commands(input.clone(), &mut |output| {
+                streamed.extend(output.commands.clone());
+                on_commands(output);
+            });
+            if let Some(timer) = timer {
+                timer.abort();
+            }
+
+            let error = match result {
+                Ok(output) => return Ok(output),
+                Err(_) if self.cancel.is_cancelled() => return Err(ClientError::Cancelled),
+                Err(e) => e,
+            };
+            let kind = if deadline.is_cancelled() {
+                ErrorKind::Timeout
+            } else {
+                ErrorKind::of(&error)
+            };
+            if !self.switch_on.contains(&kind) || active == last {
+                return Err(error);
+            }
+            warn!("{} failed: {}", name, error);
+            self.switch(active + 1, kind.to_string());
+            // the game already queued the streamed commands, the next provider
+            // answers the next request instead of adding its plan to them
+            if !streamed.is_empty() {
+                return Ok(OutputContent { commands: streamed });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+    use crate::client::models::Commands;
+    use crate::models::Direction;
+
+    #[rstest]
+    #[case(
+        ClientError::Status(StatusCode::TOO_MANY_REQUESTS),
+        ErrorKind::RateLimit
+    )]
+    #[case(
+        ClientError::Status(StatusCode::SERVICE_UNAVAILABLE),
+        ErrorKind::Server
+    )]
+    #[case(ClientError::Status(StatusCode::UNAUTHORIZED), ErrorKind::Server)]
+    #[case(
+        ClientError::Network("Request failed: error sending request".to_string()),
+        ErrorKind::Network
+    )]
+    // an answer that mentions a status is still a bad answer
+    #[case(
+        ClientError::Other("No JSON in the answer: status: 429".to_string()),
+        ErrorKind::BadAnswer
+    )]
+    fn test_error_kind(#[case] error: ClientError, #[case] expected: ErrorKind) {
+        assert_eq!(ErrorKind::of(&error), expected);
+    }
+
+    /// client that fails with the error or answers an empty plan
+    struct Stub(Option<ClientError>);
+
+    impl ApiClient for Stub {
+        fn snake_commands(&mut self, _input: InputContent) -> Result<OutputContent, ClientError> {
+            match &self.0 {
+                Some(error) => Err(error.clone()),
+                None => Ok(OutputContent { commands: vec![] }),
+            }
+        }
+    }
+
+    fn input() -> InputContent {
+        InputContent {
+            snake_head_x: 0,
+            snake_head_y: 0,
+            food_x: 1,
+            food_y: 1,
+        }
+    }
+
+    #[rstest]
+    #[case(Some(ClientError::Status(StatusCode::TOO_MANY_REQUESTS)), true, 1)]
+    #[case(Some(ClientError::Other("No JSON in the answer: hello".to_string())), false, 0)]
+    #[case(None, true, 0)]
+    fn test_switches_on_configured_errors(
+        #[case] error: Option<ClientError>,
+        #[case] answered: bool,
+        #[case] switches: usize,
+    ) {
+        let chain: Vec<(String, Box<dyn ApiClient>)> = vec![
+            ("groq".to_string(), Box::new(Stub(error))),
+            ("ollama".to_string(), Box::new(Stub(None))),
+        ];
+        let mut fallback = Fallback::new(chain, default_switch_on());
+
+        let result = fallback.snake_commands(input());
+
+        assert_eq!(result.is_ok(), answered);
+        let taken = fallback.take_switches();
+        assert_eq!(taken.len(), switches);
+        assert_eq!(fallback.active, switches);
+    }
+
+    /// streams one move up, then fails with the error
+    struct Broken(ClientError);
+
+    impl ApiClient for Broken {
+        fn snake_commands(&mut self, _input: InputContent) -> Result<OutputContent, ClientError> {
+            Err(self.0.clone())
+        }
+
+        fn stream_snake_commands(
+            &mut self,
+            _input: InputContent,
+            on_commands: &mut dyn FnMut(OutputContent),
+        ) -> Result<OutputContent, ClientError> {
+            on_commands(OutputContent {
+                commands: vec![Commands {
+                    command: Direction::Up,
+                    repeat: 1,
+                }],
+            });
+            Err(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn test_streamed_commands_answer_the_request() {
+        let chain: Vec<(String, Box<dyn ApiClient>)> = vec![
+            (
+                "groq".to_string(),
+                Box::new(Broken(ClientError::Network("connection reset".to_string()))),
+            ),
+            ("ollama".to_string(), Box::new(Stub(None))),
+        ];
+        let mut fallback = Fallback::new(chain, default_switch_on());
+        let mut forwarded = Vec::new();
+
+        let result = fallback.stream_snake_commands(input(), &mut |output| forwarded.push(output));
+
+        let streamed = OutputContent {
+            commands: vec![Commands {
+                command: Direction::Up,
+                repeat: 1,
+            }],
+        };
+        assert_eq!(result.unwrap(), streamed);
+        assert_eq!(forwarded, vec![streamed]);
+        assert_eq!(fallback.take_switches().len(), 1);
+        assert_eq!(fallback.active, 1);
+    }
+}
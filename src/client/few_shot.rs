@@ -0,0 +1,454 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use log::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde_derive::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+
+use super::{
+    correction,
+    models::{BoardView, Commands, InputContent, OutputContent},
+    prompt::{Example, PromptTemplate},
+};
+use crate::{models::Direction, snake::Step};
+
+/// states solved by the bot for every board size
+const BOT_STATES: usize = 200;
+/// the bot examples are the same in every run
+const BOT_SEED: u64 = 0;
+/// extra distance of an example where the snake moves in another direction
+const HEADING_PENALTY: i32 = 5;
+
+/// where the examples come from
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExampleSource {
+    /// states planned by the BFS bot
+    #[default]
+    Bot,
+    /// answers of earlier games that reached the food
+    Games,
+}
+
+/// Few-shot settings of a profile
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct FewShot {
+    #[serde(default)]
+    pub source: ExampleSource,
+    /// examples sent with every request
+    #[serde(default = "default_examples")]
+    pub examples: usize,
+}
+
+impl FewShot {
+    /// e.g. 3-shot-bot, stored with the games in the history
+    pub fn name(&self) -> String {
+        let source = match self.source {
+            ExampleSource::Bot => "bot",
+            ExampleSource::Games => "games",
+        };
+        format!("{}-shot-{}", self.examples, source)
+    }
+}
+
+/// how many answers had a plan that reaches the food
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlanStats {
+    pub answers: u32,
+    pub on_target: u32,
+}
+
+impl PlanStats {
+    pub fn record(&mut self, input: &InputContent, board: &BoardView, commands: &[Commands]) {
+        self.answers += 1;
+        if reaches_food(input, board, commands) {
+            self.on_target += 1;
+        }
+    }
+}
+
+impl fmt::Display for PlanStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = if self.answers == 0 {
+            0.0
+        } else {
+            100.0 * self.on_target as f64 / self.answers as f64
+        };
+        write!(
+            f,
+            "{} of {} plans reach the food ({:.0}%)",
+            self.on_target, self.answers, percent
+        )
+    }
+}
+
+/// state with a plan that reaches the food
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Solved {
+    pub input: InputContent,
+    pub width: i32,
+    pub height: i32,
+    pub heading: Option<Direction>,
+    pub commands: Vec<Commands>,
+}
+
+impl Solved {
+    fn board(&self) -> BoardView {
+        BoardView {
+            width: self.width,
+            height: self.height,
+            heading: self.heading.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// how different the state is, compares the position of the food relative to the head
+    fn distance(&self, input: &InputContent, board: &BoardView) -> i32 {
+        let dx =
+            (self.input.food_x - self.input.snake_head_x) - (input.food_x - input.snake_head_x);
+        let dy =
+            (self.input.food_y - self.input.snake_head_y) - (input.food_y - input.snake_head_y);
+        let penalty = if self.heading == board.heading {
+            0
+        } else {
+            HEADING_PENALTY
+        };
+        dx.abs() + dy.abs() + penalty
+    }
+
+    fn example(&self, template: &PromptTemplate) -> Example {
+        let output = OutputContent {
+            commands: self.commands.clone(),
+        };
+        Example {
+            user: template.render_user(&self.input, &self.board()),
+            assistant: serde_json::to_string(&output).unwrap(),
+        }
+    }
+}
+
+/// Picks the solved states most similar to the current one and sends them as examples
+pub struct ExamplePicker {
+    few_shot: FewShot,
+    solved: Vec<Solved>,
+    /// board size the bot states were solved for
+    size: (i32, i32),
+    store: Option<PathBuf>,
+}
+
+impl ExamplePicker {
+    /// the states mined from games are read from and appended to `store`
+    pub fn new(few_shot: FewShot, store: Option<PathBuf>) -> Self {
+        let solved = match (&few_shot.source, &store) {
+            (ExampleSource::Games, Some(path)) if path.exists() => load(path),
+            _ => Vec::new(),
+        };
+        Self {
+            few_shot,
+            solved,
+            size: (0, 0),
+            store,
+        }
+    }
+
+    /// examples for the request, the most similar state first
+    pub fn examples(
+        &mut self,
+        template: &PromptTemplate,
+        input: &InputContent,
+        board: &BoardView,
+    ) -> Vec<Example> {
+        if self.few_shot.source == ExampleSource::Bot && self.size != (board.width, board.height) {
+            self.size = (board.width, board.height);
+            self.solved = bot_states(board.width, board.height);
+        }
+
+        let mut nearest: Vec<&Solved> = self.solved.iter().collect();
+        nearest.sort_by_key(|s| s.distance(input, board));
+        // the most similar example goes last, right before the request
+        nearest
+            .into_iter()
+            .take(self.few_shot.examples)
+            .rev()
+            .map(|s| s.example(template))
+            .collect()
+    }
+
+    /// keeps the answer as an example when mining games and its plan reaches the food
+    pub fn learn(&mut self, input: &InputContent, board: &BoardView, commands: &[Commands]) {
+        if self.few_shot.source != ExampleSource::Games || !reaches_food(input, board, commands) {
+            return;
+        }
+        let solved = Solved {
+            input: input.clone(),
+            width: board.width,
+            height: board.height,
+            heading: board.heading.clone(),
+            commands: commands.to_vec(),
+        };
+        if let Some(path) = &self.store {
+            if let Err(e) = append(path, &solved) {
+                error!("Failed to save the example: {}", e);
+            }
+        }
+        self.solved.push(solved);
+    }
+}
+
+/// Plays the plan from the snake head, true when it gets to the food
+/// without leaving the board or running into a snake
+pub fn reaches_food(input: &InputContent, board: &BoardView, commands: &[Commands]) -> bool {
+    let mut reached = false;
+    correction::play_plan(input, board, commands, |_, step| {
+        reached = step == Step::Ate;
+        step == Step::Moved
+    });
+    reached
+}
+
+/// examples file in the user's data dir, e.g. ~/.local/share/snake-llm/examples.jsonl
+pub fn default_store_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("snake-llm").join("examples.jsonl"))
+}
+
+/// Shortest path of the bot from the head to the food around the occupied cells,
+/// the snake can't turn back on its first move
+pub fn bfs_plan(input: &InputContent, board: &BoardView) -> Option<Vec<Commands>> {
+    let (width, height) = (board.width, board.height);
+    let inside = |x: i32, y: i32| x >= 0 && y >= 0 && x < width && y < height;
+    let start = (input.snake_head_x, input.snake_head_y);
+    let food = (input.food_x, input.food_y);
+    if !inside(start.0, start.1) || !inside(food.0, food.1) {
+        return None;
+    }
+    let index = |(x, y): (i32, i32)| (y * width + x) as usize;
+
+    let mut came_from: Vec<Option<((i32, i32), Direction)>> = vec![None; (width * height) as usize];
+    let mut blocked = vec![false; (width * height) as usize];
+    for p in board.occupied.iter().filter(|p| inside(p.x, p.y)) {
+        blocked[index((p.x, p.y))] = true;
+    }
+    blocked[index(start)] = true;
+
+    let mut queue = VecDeque::from([start]);
+    while let Some(cell) = queue.pop_front() {
+        if cell == food {
+            return Some(commands(path(&came_from, index, start, food)));
+        }
+        for direction in Direction::iter() {
+            if cell == start && board.heading.as_ref() == Some(&direction.opposite()) {
+                continue;
+            }
+            let (dx, dy) = direction.delta();
+            let next = (cell.0 + dx, cell.1 + dy);
+            if !inside(next.0, next.1) || blocked[index(next)] {
+                continue;
+            }
+            blocked[index(next)] = true;
+            came_from[index(next)] = Some((cell, direction));
+            queue.push_back(next);
+        }
+    }
+    None
+}
+
+fn path(
+    came_from: &[Option<((i32, i32), Direction)>],
+    index: impl Fn((i32, i32)) -> usize,
+    start: (i32, i32),
+    food: (i32, i32),
+) -> Vec<Direction> {
+    let mut moves = Vec::new();
+    let mut cell = food;
+    while cell != start {
+        let Some((previous, direction)) = came_from[index(cell)].clone() else {
+            break;
+        };
+        moves.push(direction);
+        cell = previous;
+    }
+    moves.reverse();
+    moves
+}
+
+/// joins the same moves in a row into one command
+fn commands(moves: Vec<Direction>) -> Vec<Commands> {
+    let mut commands: Vec<Commands> = Vec::new();
+    for direction in moves {
+        match commands.last_mut() {
+            Some(last) if last.command == direction => last.repeat += 1,
+            _ => commands.push(Commands {
+                command: direction,
+                repeat: 1,
+            }),
+        }
+    }
+    commands
+}
+
+fn bot_states(width: i32, height: i32) -> Vec<Solved> {
+    if width <= 0 || height <= 0 {
+        return Vec::new();
+    }
+    let mut rng = StdRng::seed_from_u64(BOT_SEED);
+    (0..BOT_STATES)
+        .filter_map(|_| {
+            let input = InputContent {
+                snake_head_x: rng.gen_range(0..width),
+                snake_head_y: rng.gen_range(0..height),
+                food_x: rng.gen_range(0..width),
+                food_y: rng.gen_range(0..height),
+            };
+            let board = BoardView {
+                width,
+                height,
+                heading: Some(Direction::random(&mut rng)),
+                ..Default::default()
+            };
+            let commands = bfs_plan(&input, &board).filter(|c| !c.is_empty())?;
+            Some(Solved {
+                input,
+                width,
+                height,
+                heading: board.heading,
+                commands,
+            })
+        })
+        .collect()
+}
+
+fn load(path: &Path) -> Vec<Solved> {
+    match fs::read_to_string(path) {
+        Ok(text) => text
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect(),
+        Err(e) => {
+            error!("Failed to read examples {}: {}", path.display(), e);
+            Vec::new()
+        }
+    }
+}
+
+fn append(path: &Path, solved: &Solved) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let line = serde_json::to_string(solved).map_err(|e| e.to_string())?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| e.to_string())?;
+    writeln!(file, "{}", line).map_err(|e| e.to_string())
+}
+
+fn default_examples() -> usize {
+    3
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+    use crate::client::models::PromptVariant;
+    use crate::models::Point;
+
+    fn input(head: (i32, i32), food: (i32, i32)) -> InputContent {
+        InputContent {
+            snake_head_x: head.0,
+            snake_head_y: head.1,
+            food_x: food.0,
+            food_y: food.1,
+        }
+    }
+
+    #[rstest]
+    #[case(Some(Direction::Up), vec![], vec![(Direction::Up, 3)])]
+    // the wall in front of the snake is passed on the left
+    #[case(
+        Some(Direction::Up),
+        vec![Point::new(5, 6), Point::new(6, 6)],
+        vec![(Direction::Left, 1), (Direction::Up, 3), (Direction::Right, 1)]
+    )]
+    // the snake can't turn back, so it goes around
+    #[case(
+        Some(Direction::Down),
+        vec![],
+        vec![(Direction::Left, 1), (Direction::Up, 3), (Direction::Right, 1)]
+    )]
+    fn test_bfs_plan(
+        #[case] heading: Option<Direction>,
+        #[case] occupied: Vec<Point>,
+        #[case] expected: Vec<(Direction, i32)>,
+    ) {
+        let board = BoardView {
+            width: 10,
+            height: 10,
+            occupied,
+            snake: Vec::new(),
+            heading,
+        };
+
+        let plan = bfs_plan(&input((5, 5), (5, 8)), &board).unwrap();
+
+        let plan: Vec<(Direction, i32)> = plan.into_iter().map(|c| (c.command, c.repeat)).collect();
+        assert_eq!(plan, expected);
+        assert!(reaches_food(
+            &input((5, 5), (5, 8)),
+            &board,
+            &plan_commands(&plan)
+        ));
+    }
+
+    fn plan_commands(plan: &[(Direction, i32)]) -> Vec<Commands> {
+        plan.iter()
+            .map(|(command, repeat)| Commands {
+                command: command.clone(),
+                repeat: *repeat,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_examples_are_the_most_similar_states() {
+        let few_shot = FewShot {
+            source: ExampleSource::Games,
+            examples: 2,
+        };
+        let mut picker = ExamplePicker::new(few_shot, None);
+        let template = PromptTemplate::builtin(&PromptVariant::Default);
+        let board = BoardView {
+            width: 60,
+            height: 40,
+            heading: Some(Direction::Right),
+            ..Default::default()
+        };
+        // the food is 10, 3, 11 and 20 cells right of the head
+        for (head, food) in [
+            ((0, 0), (10, 0)),
+            ((5, 5), (8, 5)),
+            ((1, 1), (12, 1)),
+            ((30, 30), (50, 30)),
+        ] {
+            let plan = vec![(Direction::Right, food.0 - head.0)];
+            picker.learn(&input(head, food), &board, &plan_commands(&plan));
+        }
+
+        let examples = picker.examples(&template, &input((20, 20), (30, 20)), &board);
+
+        let users: Vec<String> = examples.into_iter().map(|e| e.user).collect();
+        let expected = vec![
+            template.render_user(&input((1, 1), (12, 1)), &board),
+            template.render_user(&input((0, 0), (10, 0)), &board),
+        ];
+        assert_eq!(users, expected);
+    }
+}
@@ -1,109 +1,422 @@
-use std::time::Duration;
-
-use reqwest::{
-    blocking::{Client, Response},
-    StatusCode,
+use std::{
+    io::{BufRead, BufReader},
+    time::Duration,
 };
+
+use log::warn;
+use reqwest::{Client, RequestBuilder};
 use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
 
 use super::{
-    models::{self, InputContent, OutputContent, Role, SYSTEM_PROMPT},
-    ApiClient,
+    chat::{self, ChatApi},
+    error::ClientError,
+    http::BodyReader,
+    models::{Message, OutputContent, Role},
+    stream::Streamed,
+    usage::Tokens,
 };
 
+/// context size of gemini-1.5-pro
+const DEFAULT_CONTEXT_TOKENS: usize = 1_048_576;
+/// longest answer, a plan is much shorter
+const MAX_ANSWER_TOKENS: u32 = 1024;
+
 #[derive(Serialize, Deserialize, Debug)]
-struct GeminiRequest {
-    model: String,
-    messages: Vec<models::Message>,
-    stream: bool,
+#[serde(rename_all = "camelCase")]
+struct GenerateContentRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<Content>,
+    /// only user and model turns, the system prompt is a field of its own
+    contents: Vec<Content>,
+    generation_config: GenerationConfig,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-struct GeminiResponse {
-    message: models::Message,
+struct Content {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
+    #[serde(default)]
+    parts: Vec<Part>,
 }
 
-pub struct GeminiClient {
-    client: Client,
-    url: String,
-    token: String,
-    request: GeminiRequest,
+#[derive(Serialize, Deserialize, Debug)]
+struct Part {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    max_output_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_schema: Option<Value>,
 }
 
-impl ApiClient for GeminiClient {
-    fn snake_commands(&mut self, input: InputContent) -> Result<OutputContent, String> {
-        self.snake_commands(input)
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GenerateContentResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    usage_metadata: Option<UsageMetadata>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Candidate {
+    content: Option<Content>,
+    finish_reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    candidates_token_count: u64,
+}
+
+impl GenerateContentResponse {
+    /// text of the answer, an answer cut off or blocked has no usable plan
+    fn text(&self) -> Result<String, String> {
+        let Some(candidate) = self.candidates.first() else {
+            return Err("No response form api".to_string());
+        };
+        match candidate.finish_reason.as_deref() {
+            Some("MAX_TOKENS") => {
+                return Err("The answer was cut off at maxOutputTokens".to_string())
+            }
+            Some("SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT") => {
+                return Err("The model refused to answer".to_string())
+            }
+            _ => {}
+        }
+        Ok(candidate
+            .content
+            .iter()
+            .flat_map(|content| &content.parts)
+            .map(|part| part.text.as_str())
+            .collect())
+    }
+
+    fn tokens(&self) -> Option<Tokens> {
+        self.usage_metadata.as_ref().map(|usage| Tokens {
+            prompt_tokens: usage.prompt_token_count,
+            completion_tokens: usage.candidates_token_count,
+        })
     }
 }
 
-impl GeminiClient {
-    pub fn new(url: String, token: String) -> Self {
+/// Gemini generateContent API, `url` is the models endpoint the model name is appended to
+pub struct GeminiApi {
+    url: String,
+    token: String,
+    model: String,
+    timeout: Option<Duration>,
+    temperature: Option<f32>,
+    response_schema: Option<Value>,
+}
+
+impl GeminiApi {
+    pub fn new(url: String, token: String, model: String) -> Self {
         Self {
-            client: Client::new(),
             url,
             token,
-            request: GeminiRequest {
-                model: "gemini-1.5-pro".to_owned(),
-                messages: vec![models::Message {
-                    role: Role::System.as_string(),
-                    content: SYSTEM_PROMPT.to_string(),
-                }],
-                stream: false,
-            },
+            model,
+            timeout: None,
+            temperature: None,
+            response_schema: Some(response_schema()),
         }
     }
 
-    pub fn snake_commands(&mut self, input: InputContent) -> Result<OutputContent, String> {
-        self.request.messages.push(models::Message {
-            role: Role::User.as_string(),
-            content: serde_json::to_string(&input).unwrap(),
-        });
+    /// streamed answers are sent as server-sent events of whole responses
+    fn endpoint(&self, stream: bool) -> String {
+        let url = self.url.trim_end_matches('/');
+        if stream {
+            format!("{}/{}:streamGenerateContent?alt=sse", url, self.model)
+        } else {
+            format!("{}/{}:generateContent", url, self.model)
+        }
+    }
+}
 
-        let body = match serde_json::to_string(&self.request) {
-            Ok(body) => body,
-            Err(e) => return Err(format!("Failed to serialize request: {}", e)),
-        };
+impl ChatApi for GeminiApi {
+    const CONTEXT_TOKENS: usize = DEFAULT_CONTEXT_TOKENS;
 
-        let resp = self.post(&self.url, body);
-        let status = resp.status();
+    fn model(&self) -> &str {
+        &self.model
+    }
 
-        if status != StatusCode::OK {
-            return Err(format!("Request failed with status: {}", status));
-        }
+    fn set_temperature(&mut self, temperature: f32) {
+        self.temperature = Some(temperature);
+    }
 
-        let resp_body = match resp.text() {
-            Ok(body) => body,
-            Err(e) => return Err(format!("Failed to get response body: {}", e)),
-        };
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    fn set_structured_output(&mut self, enabled: bool) {
+        self.response_schema = enabled.then(response_schema);
+    }
 
-        let resp: Option<GeminiResponse> = match serde_json::from_str(&resp_body) {
+    /// Sends the messages with the system prompt moved to its own field,
+    /// the assistant turns are the model's
+    fn request(
+        &self,
+        client: &Client,
+        messages: &[Message],
+        stream: bool,
+    ) -> Result<RequestBuilder, String> {
+        let (system, messages): (Vec<&Message>, Vec<&Message>) = messages
+            .iter()
+            .partition(|m| m.role == Role::System.as_string());
+        let system = system
+            .into_iter()
+            .map(|m| m.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let body = chat::body(&GenerateContentRequest {
+            system_instruction: (!system.is_empty()).then(|| content(None, system)),
+            contents: messages
+                .into_iter()
+                .map(|m| {
+                    let role = if m.role == Role::Assistant.as_string() {
+                        "model"
+                    } else {
+                        "user"
+                    };
+                    content(Some(role), m.content.clone())
+                })
+                .collect(),
+            generation_config: GenerationConfig {
+                max_output_tokens: MAX_ANSWER_TOKENS,
+                temperature: self.temperature,
+                response_mime_type: self
+                    .response_schema
+                    .as_ref()
+                    .map(|_| "application/json".to_string()),
+                response_schema: self.response_schema.clone(),
+            },
+        })?;
+
+        let mut request = client
+            .post(self.endpoint(stream))
+            .header("x-goog-api-key", &self.token)
+            .header("content-type", "application/json");
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+        Ok(request.body(body))
+    }
+
+    fn read_response(&self, body: &str) -> Result<(String, Option<Tokens>), String> {
+        let resp: GenerateContentResponse = match serde_json::from_str(body) {
             Ok(r) => r,
             Err(e) => {
                 return Err(format!("Failed to parse response body {}", e));
             }
         };
+        Ok((resp.text()?, resp.tokens()))
+    }
 
-        if let Some(resp) = resp {
-            let _res: OutputContent = match serde_json::from_str(&resp.message.content) {
-                Ok(res) => return Ok(res),
-                Err(e) => {
-                    return Err(format!(
-                        "Failed to parse messages body: {}, response: {:?}",
-                        e, resp.message.content
-                    ));
-                }
+    /// Every event is a response with the next piece of the answer,
+    /// the last one has the usage of the whole call
+    fn read_stream(
+        &self,
+        body: BodyReader,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<Streamed, ClientError> {
+        let mut content = String::new();
+        let mut tokens = None;
+        for line in BufReader::new(body).lines() {
+            let line = line?;
+            let Some(data) = line.strip_prefix("data:") else {
+                continue;
             };
-        } else {
-            return Err("No response form api".to_string());
+            let chunk: GenerateContentResponse = serde_json::from_str(data.trim())
+                .map_err(|e| format!("Failed to parse stream chunk {}: {}", e, data))?;
+            let piece = chunk.text()?;
+            if !piece.is_empty() {
+                content.push_str(&piece);
+                on_chunk(&piece);
+            }
+            tokens = chunk.tokens().or(tokens);
+        }
+        Ok(Streamed { content, tokens })
+    }
+
+    fn drop_structured_output(&mut self, error: &str) -> bool {
+        if self.response_schema.is_none()
+            || !chat::mentions(error, &["responseSchema", "response_schema"])
+        {
+            return false;
+        }
+        warn!(
+            "Structured output is not supported by {}, falling back to the prompt",
+            self.url
+        );
+        self.response_schema = None;
+        true
+    }
+}
+
+/// the answer schema without `additionalProperties`, the API only takes a subset of JSON schema
+fn response_schema() -> Value {
+    fn strip(value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                map.remove("additionalProperties");
+                map.values_mut().for_each(strip);
+            }
+            Value::Array(items) => items.iter_mut().for_each(strip),
+            _ => {}
+        }
+    }
+    let mut schema = OutputContent::json_schema();
+    strip(&mut schema);
+    schema
+}
+
+fn content(role: Option<&str>, text: String) -> Content {
+    Content {
+        role: role.map(str::to_string),
+        parts: vec![Part { text }],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+    use crate::client::{
+        chat::ChatClient,
+        http::stand_in_server,
+        memory::HistoryPolicy,
+        models::{BoardView, Commands, InputContent},
+        prompt::PromptTemplate,
+        ApiClient,
+    };
+    use crate::models::Direction;
+
+    /// client with a one line template on a 10x10 board
+    fn client(url: String) -> ChatClient<GeminiApi> {
+        let template = PromptTemplate {
+            name: "test".to_string(),
+            system: "Play snake.".to_string(),
+            user: "Head at {{snake_head_x}},{{snake_head_y}}, food at {{food_x}},{{food_y}}."
+                .to_string(),
+            examples: Vec::new(),
         };
+        let mut client = ChatClient::new(GeminiApi::new(
+            url,
+            "test-key".to_string(),
+            "gemini-1.5-flash".to_string(),
+        ))
+        .with_template(template);
+        client.observe_board(&BoardView {
+            width: 10,
+            height: 10,
+            occupied: Vec::new(),
+            snake: Vec::new(),
+            heading: None,
+        });
+        client
+    }
+
+    fn input() -> InputContent {
+        InputContent {
+            snake_head_x: 5,
+            snake_head_y: 5,
+            food_x: 5,
+            food_y: 8,
+        }
+    }
+
+    #[test]
+    fn test_generate_content_round_trip() {
+        let (url, server) = stand_in_server(
+            "/v1beta/models",
+            include_str!("fixtures/gemini/response.json"),
+            1,
+        );
+        let mut client = client(url).with_temperature(0.5);
+
+        let output = client.snake_commands(input()).unwrap();
+
+        assert_eq!(
+            output.commands,
+            vec![Commands {
+                command: Direction::Up,
+                repeat: 3
+            }]
+        );
+        assert_eq!(client.usage().prompt_tokens, 21);
+        let (headers, body) = server.join().unwrap().remove(0);
+        assert!(headers[0].starts_with("post /v1beta/models/gemini-1.5-flash:generatecontent "));
+        assert!(headers.contains(&"x-goog-api-key: test-key".to_string()));
+        let body: Value = serde_json::from_str(&body).unwrap();
+        let expected: Value =
+            serde_json::from_str(include_str!("fixtures/gemini/request.json")).unwrap();
+        assert_eq!(body, expected);
     }
 
-    fn post(&self, url: &str, body: String) -> Response {
-        self.client
-            .post(url)
-            .timeout(Duration::from_secs(120))
-            .body(body)
-            .send()
+    #[test]
+    fn test_history_is_bounded() {
+        let (url, server) = stand_in_server(
+            "/v1beta/models",
+            include_str!("fixtures/gemini/response.json"),
+            3,
+        );
+        let mut client = client(url).with_history(HistoryPolicy::Window { turns: 1 });
+
+        for _ in 0..3 {
+            client.snake_commands(input()).unwrap();
+        }
+
+        let contents: Vec<usize> = server
+            .join()
             .unwrap()
+            .iter()
+            .map(|(_, body)| {
+                let body: Value = serde_json::from_str(body).unwrap();
+                body["contents"].as_array().unwrap().len()
+            })
+            .collect();
+        // the request, then the last turn and the request
+        assert_eq!(contents, vec![1, 3, 3]);
+    }
+
+    #[rstest]
+    #[case(
+        r#"{"error":{"code":400,"message":"Invalid JSON payload received. Unknown name \"responseSchema\" at 'generation_config': Cannot find field.","status":"INVALID_ARGUMENT"}}"#,
+        true
+    )]
+    #[case(
+        r#"{"error":{"code":400,"message":"API key not valid. Please pass a valid API key.","status":"INVALID_ARGUMENT"}}"#,
+        false
+    )]
+    fn test_drop_structured_output(#[case] error: &str, #[case] dropped: bool) {
+        let mut api = GeminiApi::new(String::new(), String::new(), String::new());
+
+        assert_eq!(api.drop_structured_output(error), dropped);
+        assert_eq!(api.response_schema.is_none(), dropped);
+    }
+
+    #[test]
+    fn test_answer_cut_off() {
+        let mut response: GenerateContentResponse =
+            serde_json::from_str(include_str!("fixtures/gemini/response.json")).unwrap();
+        assert!(response.text().is_ok());
+
+        response.candidates[0].finish_reason = Some("MAX_TOKENS".to_string());
+
+        assert!(response.text().is_err());
     }
 }
@@ -0,0 +1,90 @@
+use serde_json::Value;
+
+/// GBNF grammar of llama.cpp that only accepts JSON matching the schema.
+/// Supports the schemas of the answers: objects, arrays, strings, enums of strings and integers.
+/// All properties of an object are required and written sorted by name.
+/// Integers have at most 9 digits and whitespace is bounded, so the model can't run on forever,
+/// the repeats that don't fit the board are clamped by the parser
+pub fn from_schema(schema: &Value) -> Result<String, String> {
+    let mut rules = Vec::new();
+    rule("root", schema, &mut rules)?;
+    rules.push("ws ::= | \" \" | \"\\n\" [ \\t]{0,20}".to_string());
+    Ok(rules.join("\n"))
+}
+
+/// adds the rule `name` and the rules it refers to
+fn rule(name: &str, schema: &Value, rules: &mut Vec<String>) -> Result<(), String> {
+    let index = rules.len();
+    rules.push(String::new());
+
+    let body = if let Some(values) = schema["enum"].as_array() {
+        values
+            .iter()
+            .map(|v| literal(&v.to_string()))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    } else {
+        match schema["type"].as_str() {
+            Some("object") => {
+                let properties = schema["properties"]
+                    .as_object()
+                    .ok_or_else(|| format!("object {} has no properties", name))?;
+                let mut fields = Vec::new();
+                for (key, property) in properties {
+                    let field = format!("{}-{}", name, key);
+                    rule(&field, property, rules)?;
+                    fields.push(format!(
+                        "{} ws \":\" ws {}",
+                        literal(&format!("\"{}\"", key)),
+                        field
+                    ));
+                }
+                format!("\"{{\" ws {} ws \"}}\"", fields.join(" ws \",\" ws "))
+            }
+            Some("array") => {
+                let item = format!("{}-item", name);
+                rule(&item, &schema["items"], rules)?;
+                format!("\"[\" ws ({} (ws \",\" ws {})*)? ws \"]\"", item, item)
+            }
+            Some("integer") => "\"0\" | [1-9] [0-9]{0,8}".to_string(),
+            Some("string") => "\"\\\"\" [^\"\\\\]* \"\\\"\"".to_string(),
+            other => return Err(format!("unsupported type {:?} of {}", other, name)),
+        }
+    };
+    rules[index] = format!("{} ::= {}", name, body);
+    Ok(())
+}
+
+/// GBNF string literal of the text
+fn literal(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::models::OutputContent;
+
+    #[test]
+    fn test_grammar_of_the_answer() {
+        let grammar = from_schema(&OutputContent::json_schema()).unwrap();
+
+        let expected = [
+            r#"root ::= "{" ws "\"commands\"" ws ":" ws root-commands ws "}""#,
+            r#"root-commands ::= "[" ws (root-commands-item (ws "," ws root-commands-item)*)? ws "]""#,
+            r#"root-commands-item ::= "{" ws "\"command\"" ws ":" ws root-commands-item-command ws "," ws "\"repeat\"" ws ":" ws root-commands-item-repeat ws "}""#,
+            r#"root-commands-item-command ::= "\"up\"" | "\"down\"" | "\"left\"" | "\"right\"""#,
+            r#"root-commands-item-repeat ::= "0" | [1-9] [0-9]{0,8}"#,
+            r#"ws ::= | " " | "\n" [ \t]{0,20}"#,
+        ]
+        .join("\n");
+        assert_eq!(grammar, expected);
+    }
+
+    #[test]
+    fn test_unsupported_type() {
+        let schema = serde_json::json!({"type": "boolean"});
+
+        assert!(from_schema(&schema).is_err());
+    }
+}
@@ -1,31 +1,46 @@
-use reqwest::{
-    blocking::{Client, Response},
-    StatusCode,
-};
+use std::time::Duration;
+
+use log::warn;
+use reqwest::{Client, RequestBuilder};
 use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
 use super::{
-    models::{self, Choice, InputContent, Message, OutputContent, Role, SYSTEM_PROMPT},
-    ApiClient,
+    chat::{self, ChatApi},
+    error::ClientError,
+    http::BodyReader,
+    models::{self, Choice, Message, OutputContent},
+    stream::{self, Streamed},
+    usage::Tokens,
 };
 
+/// context size of the models without it in the name
+const DEFAULT_CONTEXT_TOKENS: usize = 8192;
+
 #[derive(Serialize, Deserialize, Debug)]
 struct GroqRequest {
     messages: Vec<models::Message>,
     model: String,
     temperature: f32,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<Value>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct GroqResponse {
     choices: Vec<Choice>,
+    usage: Option<Tokens>,
 }
 
-pub struct GroqClient {
-    client: Client,
+/// OpenAI compatible chat completions of Groq
+pub struct GroqApi {
     url: String,
     token: String,
-    request: GroqRequest,
+    model: String,
+    temperature: f32,
+    timeout: Option<Duration>,
+    response_format: Option<Value>,
 }
 
 #[allow(dead_code)]
@@ -45,91 +60,126 @@ impl GroqModels {
     }
 }
 
-impl ApiClient for GroqClient {
-    fn snake_commands(&mut self, input: InputContent) -> Result<OutputContent, String> {
-        self.snake_commands(input)
-    }
-}
-
-impl GroqClient {
-    pub fn new(url: String, token: String) -> Self {
+impl GroqApi {
+    pub fn new(url: String, token: String, model: Option<String>) -> Self {
         Self {
-            client: Client::new(),
             url,
             token,
-            request: default_request(GroqModels::Llama3b70),
+            model: model.unwrap_or_else(|| GroqModels::Llama3b70.as_string()),
+            temperature: 1.0,
+            timeout: None,
+            response_format: Some(structured_output()),
         }
     }
+}
 
-    pub fn snake_commands(&mut self, input: InputContent) -> Result<OutputContent, String> {
-        self.add_message_to_request(input);
+impl ChatApi for GroqApi {
+    const CONTEXT_TOKENS: usize = DEFAULT_CONTEXT_TOKENS;
 
-        let body = match serde_json::to_string(&self.request) {
-            Ok(body) => body,
-            Err(e) => return Err(format!("Failed to serialize request: {}", e)),
-        };
+    fn model(&self) -> &str {
+        &self.model
+    }
 
-        let resp = self.post(&self.url, body);
-        let status = resp.status();
+    fn set_temperature(&mut self, temperature: f32) {
+        self.temperature = temperature;
+    }
 
-        if status != StatusCode::OK {
-            return Err(format!("Request failed with status: {}", status));
-        }
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
 
-        let resp_body = match resp.text() {
-            Ok(body) => body,
-            Err(e) => return Err(format!("Failed to get response body: {}", e)),
-        };
+    fn set_structured_output(&mut self, enabled: bool) {
+        self.response_format = enabled.then(structured_output);
+    }
 
-        let resp: Option<GroqResponse> = match serde_json::from_str(&resp_body) {
+    fn request(
+        &self,
+        client: &Client,
+        messages: &[Message],
+        stream: bool,
+    ) -> Result<RequestBuilder, String> {
+        let body = chat::body(&GroqRequest {
+            messages: messages.to_vec(),
+            model: self.model.clone(),
+            temperature: self.temperature,
+            stream,
+            response_format: self.response_format.clone(),
+        })?;
+        let mut request = client.post(&self.url).bearer_auth(&self.token);
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+        Ok(request.body(body))
+    }
+
+    fn read_response(&self, body: &str) -> Result<(String, Option<Tokens>), String> {
+        let resp: Option<GroqResponse> = match serde_json::from_str(body) {
             Ok(r) => r,
             Err(e) => {
                 return Err(format!("Failed to parse response body {}", e));
             }
         };
-        if let Some(resp) = resp {
-            let _res: OutputContent = match serde_json::from_str(&resp.choices[0].message.content) {
-                Ok(res) => return Ok(res),
-                Err(e) => {
-                    return Err(format!(
-                        "Failed to parse messages body: {}, response: {:?}",
-                        e, resp.choices[0].message
-                    ));
-                }
-            };
-        } else {
-            return Err("No response form api".to_string());
-        };
+        match resp {
+            Some(resp) => Ok((resp.choices[0].message.content.clone(), resp.usage)),
+            None => Err("No response form api".to_string()),
+        }
     }
 
-    fn add_message_to_request(&mut self, input: InputContent) {
-        if self.request.messages.len() > 1 {
-            self.request.messages.pop();
-        }
-        self.request.messages.push(Message {
-            role: Role::User.as_string(),
-            content: serde_json::to_string(&input).unwrap(),
-        });
+    fn read_stream(
+        &self,
+        body: BodyReader,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<Streamed, ClientError> {
+        stream::read_sse(body, on_chunk)
     }
 
-    fn post(&self, url: &str, body: String) -> Response {
-        self.client
-            .post(url)
-            .bearer_auth(&self.token)
-            .body(body)
-            .send()
-            .unwrap()
+    fn drop_structured_output(&mut self, error: &str) -> bool {
+        if self.response_format.is_none()
+            || !chat::mentions(error, &["response_format", "json_schema"])
+        {
+            return false;
+        }
+        warn!(
+            "Structured output is not supported by {}, falling back to the prompt",
+            self.url
+        );
+        self.response_format = None;
+        true
     }
 }
 
-fn default_request(model: GroqModels) -> GroqRequest {
-    GroqRequest {
-        temperature: 1.0,
-        messages: vec![Message {
-            role: Role::System.as_string(),
-            content: SYSTEM_PROMPT.to_string(),
-        }],
-        model: model.as_string(),
-    }
+fn structured_output() -> Value {
+    json!({
+        "type": "json_schema",
+        "json_schema": {
+            "name": "snake_commands",
+            "strict": true,
+            "schema": OutputContent::json_schema(),
+        }
+    })
 }
 
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[case(
+        r#"{"error":{"message":"response_format` json_schema` is not supported with this model","type":"invalid_request_error"}}"#,
+        true
+    )]
+    // a bad request for another reason keeps the schema
+    #[case(
+        r#"{"error":{"message":"Please reduce the length of the messages or completion.","type":"invalid_request_error"}}"#,
+        false
+    )]
+    #[case("", false)]
+    fn test_drop_structured_output(#[case] error: &str, #[case] dropped: bool) {
+        let mut api = GroqApi::new(String::new(), String::new(), None);
+
+        assert_eq!(api.drop_structured_output(error), dropped);
+        assert_eq!(api.response_format.is_none(), dropped);
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    future::Future,
+    io::{self, Read},
+    sync::OnceLock,
+};
+
+use reqwest::{RequestBuilder, Response};
+use tokio::runtime::{Builder, Runtime};
+use tokio_util::sync::CancellationToken;
+
+use super::error::ClientError;
+
+/// error of the requests stopped by their cancellation token
+pub const CANCELLED: &str = "Request cancelled";
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+/// Runtime of all requests, created on the first use
+pub fn runtime() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("snake-llm-http")
+            .build()
+            .expect("Failed to start the async runtime")
+    })
+}
+
+/// Sends the request, the connection is dropped as soon as `cancel` is cancelled
+pub fn send(request: RequestBuilder, cancel: &CancellationToken) -> Result<Response, ClientError> {
+    block_on(cancel, async {
+        request
+            .send()
+            .await
+            .map_err(|e| ClientError::Network(format!("Request failed: {}", e)))
+    })
+}
+
+pub fn text(response: Response, cancel: &CancellationToken) -> Result<String, ClientError> {
+    block_on(cancel, async {
+        response
+            .text()
+            .await
+            .map_err(|e| ClientError::Network(format!("Failed to get response body: {}", e)))
+    })
+}
+
+/// Runs the future on the runtime from a blocking thread
+fn block_on<T>(
+    cancel: &CancellationToken,
+    future: impl Future<Output = Result<T, ClientError>>,
+) -> Result<T, ClientError> {
+    runtime().block_on(async {
+        tokio::select! {
+            biased;
+            _ = cancel.cancelled() => Err(ClientError::Cancelled),
+            result = future => result,
+        }
+    })
+}
+
+/// Body of a streamed response, read chunk by chunk until the end or the cancellation
+pub struct BodyReader {
+    response: Response,
+    cancel: CancellationToken,
+    chunk: Vec<u8>,
+    read: usize,
+}
+
+impl BodyReader {
+    pub fn new(response: Response, cancel: &CancellationToken) -> Self {
+        Self {
+            response,
+            cancel: cancel.clone(),
+            chunk: Vec::new(),
+            read: 0,
+        }
+    }
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.read == self.chunk.len() {
+            let response = &mut self.response;
+            let chunk = block_on(&self.cancel, async {
+                response
+                    .chunk()
+                    .await
+                    .map_err(|e| ClientError::Network(format!("Failed to read stream: {}", e)))
+            })
+            .map_err(io::Error::other)?;
+            match chunk {
+                Some(chunk) => {
+                    self.chunk = chunk.to_vec();
+                    self.read = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len() - self.read);
+        buf[..len].copy_from_slice(&self.chunk[self.read..self.read + len]);
+        self.read += len;
+        Ok(len)
+    }
+}
+
+/// headers and body of a request the stand-in server got
+#[cfg(test)]
+pub type Received = (Vec<String>, String);
+
+/// Answers `requests` requests with `response`,
+/// returns the url and the headers and body of every request it got
+#[cfg(test)]
+pub fn stand_in_server(
+    path: &str,
+    response: &'static str,
+    requests: usize,
+) -> (String, std::thread::JoinHandle<Vec<Received>>) {
+    use std::io::{BufRead, BufReader, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}{}", listener.local_addr().unwrap(), path);
+    let server = std::thread::spawn(move || {
+        let mut received = Vec::new();
+        for _ in 0..requests {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                headers.push(line.trim().to_lowercase());
+            }
+            let length: usize = headers
+                .iter()
+                .find_map(|h| h.strip_prefix("content-length: "))
+                .unwrap()
+                .parse()
+                .unwrap();
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                response.len(),
+                response
+            )
+            .unwrap();
+            received.push((headers, String::from_utf8(body).unwrap()));
+        }
+        received
+    });
+    (url, server)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn test_send_stops_when_cancelled() {
+        // accepts the connection but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let cancel = CancellationToken::new();
+        let token = cancel.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            token.cancel();
+        });
+
+        let result = send(reqwest::Client::new().post(url), &cancel);
+
+        assert_eq!(result.err(), Some(ClientError::Cancelled));
+        drop(listener);
+    }
+}
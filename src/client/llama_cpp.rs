@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+use log::warn;
+use reqwest::{Client, RequestBuilder};
+use serde_derive::{Deserialize, Serialize};
+
+use super::{
+    chat::{self, ChatApi},
+    error::ClientError,
+    grammar,
+    http::BodyReader,
+    models::{Message, OutputContent, Role},
+    stream::{self, Streamed},
+    usage::Tokens,
+};
+
+/// default context size of llama-server
+const DEFAULT_CONTEXT_TOKENS: usize = 4096;
+/// longest answer, a plan is much shorter
+const MAX_ANSWER_TOKENS: i32 = 256;
+/// the model starts to write the next turn of the user after its answer
+const STOP: &str = "\nUser:";
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CompletionRequest {
+    prompt: String,
+    n_predict: i32,
+    stream: bool,
+    stop: Vec<String>,
+    cache_prompt: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    grammar: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CompletionResponse {
+    content: String,
+    tokens_evaluated: Option<u64>,
+    tokens_predicted: Option<u64>,
+}
+
+impl CompletionResponse {
+    fn tokens(&self) -> Option<Tokens> {
+        Some(Tokens {
+            prompt_tokens: self.tokens_evaluated?,
+            completion_tokens: self.tokens_predicted?,
+        })
+    }
+}
+
+/// Native `/completion` endpoint of llama.cpp's llama-server.
+/// The conversation is sent as one prompt, the answer is limited by a GBNF grammar
+pub struct LlamaCppApi {
+    url: String,
+    /// only used to guess the context size, the server runs the model it was started with
+    model: String,
+    timeout: Duration,
+    temperature: Option<f32>,
+    grammar: Option<String>,
+}
+
+impl LlamaCppApi {
+    pub fn new(url: String, model: String) -> Self {
+        Self {
+            url,
+            model,
+            timeout: Duration::from_secs(60 * 10),
+            temperature: None,
+            grammar: Some(answer_grammar()),
+        }
+    }
+}
+
+impl ChatApi for LlamaCppApi {
+    const CONTEXT_TOKENS: usize = DEFAULT_CONTEXT_TOKENS;
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn set_temperature(&mut self, temperature: f32) {
+        self.temperature = Some(temperature);
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// sends the grammar of OutputContent, so the model can only answer with valid commands
+    fn set_structured_output(&mut self, enabled: bool) {
+        self.grammar = enabled.then(answer_grammar);
+    }
+
+    fn request(
+        &self,
+        client: &Client,
+        messages: &[Message],
+        stream: bool,
+    ) -> Result<RequestBuilder, String> {
+        let body = chat::body(&CompletionRequest {
+            prompt: prompt(messages),
+            n_predict: MAX_ANSWER_TOKENS,
+            stream,
+            stop: vec![STOP.to_string()],
+            cache_prompt: true,
+            temperature: self.temperature,
+            grammar: self.grammar.clone(),
+        })?;
+        Ok(client.post(&self.url).timeout(self.timeout).body(body))
+    }
+
+    fn read_response(&self, body: &str) -> Result<(String, Option<Tokens>), String> {
+        let resp: CompletionResponse = match serde_json::from_str(body) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(format!("Failed to parse response body {}", e));
+            }
+        };
+        Ok((resp.content.trim().to_string(), resp.tokens()))
+    }
+
+    fn read_stream(
+        &self,
+        body: BodyReader,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<Streamed, ClientError> {
+        let mut streamed = stream::read_completion_sse(body, on_chunk)?;
+        streamed.content = streamed.content.trim().to_string();
+        Ok(streamed)
+    }
+
+    fn drop_structured_output(&mut self, error: &str) -> bool {
+        if self.grammar.is_none() || !chat::mentions(error, &["grammar"]) {
+            return false;
+        }
+        warn!(
+            "Grammars are not supported by {}, falling back to the prompt",
+            self.url
+        );
+        self.grammar = None;
+        true
+    }
+}
+
+fn answer_grammar() -> String {
+    grammar::from_schema(&OutputContent::json_schema()).expect("the answer schema is supported")
+}
+
+/// The conversation as one text, the system prompt first,
+/// then the turns as `User:` and `Assistant:` lines, ending where the model answers
+fn prompt(messages: &[Message]) -> String {
+    let mut prompt = String::new();
+    for message in messages {
+        if message.role == Role::System.as_string() {
+            prompt.push_str(&message.content);
+            prompt.push_str("\n\n");
+        } else if message.role == Role::Assistant.as_string() {
+            prompt.push_str(&format!("Assistant: {}\n", message.content));
+        } else {
+            prompt.push_str(&format!("User: {}\n", message.content));
+        }
+    }
+    prompt.push_str("Assistant:");
+    prompt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt() {
+        let message = |role: Role, content: &str| Message {
+            role: role.as_string(),
+            content: content.to_string(),
+        };
+        let messages = vec![
+            message(Role::System, "Play snake."),
+            message(Role::User, "{\"food_x\": 1}"),
+            message(Role::Assistant, "{\"commands\": []}"),
+            message(Role::User, "{\"food_x\": 2}"),
+        ];
+
+        assert_eq!(
+            prompt(&messages),
+            "Play snake.\n\nUser: {\"food_x\": 1}\nAssistant: {\"commands\": []}\nUser: {\"food_x\": 2}\nAssistant:"
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::VecDeque;
+
+use serde_derive::Deserialize;
+
+use super::{
+    models::{Message, Role},
+    prompt::Example,
+};
+
+/// tokens kept free for the answer of the model
+const ANSWER_TOKENS: usize = 256;
+/// tokens of the role and separators of every message
+const MESSAGE_OVERHEAD: usize = 4;
+/// older turns kept in the summary
+const SUMMARY_LINES: usize = 20;
+
+/// which of the previous requests are sent with the next one
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum HistoryPolicy {
+    /// only the system prompt and the current request
+    #[default]
+    Stateless,
+    /// the last `turns` requests with their answers
+    Window { turns: usize },
+    /// the last `turns` requests, the older ones folded into a short summary
+    Summary { turns: usize },
+}
+
+struct Turn {
+    user: String,
+    assistant: String,
+}
+
+/// Messages sent to the model, shared by the clients.
+/// Keeps the previous turns according to the policy and drops the oldest ones
+/// when the request would not fit into the context of the model
+pub struct Conversation {
+    policy: HistoryPolicy,
+    context_tokens: usize,
+    system_prompt: String,
+    examples: Vec<Example>,
+    turns: VecDeque<Turn>,
+    summary: VecDeque<String>,
+    folded: usize,
+}
+
+impl Conversation {
+    pub fn new(system_prompt: &str, context_tokens: usize) -> Self {
+        Self {
+            policy: HistoryPolicy::default(),
+            context_tokens,
+            system_prompt: system_prompt.to_string(),
+            examples: Vec::new(),
+            turns: VecDeque::new(),
+            summary: VecDeque::new(),
+            folded: 0,
+        }
+    }
+
+    pub fn set_policy(&mut self, policy: HistoryPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn set_context_tokens(&mut self, context_tokens: usize) {
+        self.context_tokens = context_tokens;
+    }
+
+    pub fn set_system_prompt(&mut self, prompt: &str) {
+        self.system_prompt = prompt.to_string();
+    }
+
+    /// few-shot examples sent after the system prompt
+    pub fn set_examples(&mut self, examples: Vec<Example>) {
+        self.examples = examples;
+    }
+
+    /// messages of the request with the given user message
+    pub fn messages(&self, user: &str) -> Vec<Message> {
+        let system = match self.summary_text() {
+            Some(summary) => format!("{}\n{}", self.system_prompt, summary),
+            None => self.system_prompt.clone(),
+        };
+        let fixed = estimate_tokens(&system)
+            + estimate_tokens(user)
+            + 2 * MESSAGE_OVERHEAD
+            + self
+                .examples
+                .iter()
+                .map(|e| pair_tokens(&e.user, &e.assistant))
+                .sum::<usize>();
+        let mut budget = self
+            .context_tokens
+            .saturating_sub(ANSWER_TOKENS)
+            .saturating_sub(fixed);
+
+        // the newest turns are the most useful, older ones are dropped first
+        let mut kept = Vec::new();
+        for turn in self.turns.iter().rev() {
+            let tokens = pair_tokens(&turn.user, &turn.assistant);
+            if tokens > budget {
+                break;
+            }
+            budget -= tokens;
+            kept.push(turn);
+        }
+
+        let mut messages = vec![message(Role::System, system)];
+        for example in &self.examples {
+            messages.push(message(Role::User, example.user.clone()));
+            messages.push(message(Role::Assistant, example.assistant.clone()));
+        }
+        for turn in kept.into_iter().rev() {
+            messages.push(message(Role::User, turn.user.clone()));
+            messages.push(message(Role::Assistant, turn.assistant.clone()));
+        }
+        messages.push(message(Role::User, user.to_string()));
+        messages
+    }
+
+    /// remembers the answer to the request
+    pub fn record(&mut self, user: String, assistant: String) {
+        let limit = match self.policy {
+            HistoryPolicy::Stateless => return,
+            HistoryPolicy::Window { turns } | HistoryPolicy::Summary { turns } => turns,
+        };
+        self.turns.push_back(Turn { user, assistant });
+
+        while self.turns.len() > limit {
+            let Some(turn) = self.turns.pop_front() else {
+                break;
+            };
+            if let HistoryPolicy::Summary { .. } = self.policy {
+                self.fold(turn);
+            }
+        }
+    }
+
+    fn fold(&mut self, turn: Turn) {
+        self.folded += 1;
+        self.summary.push_back(format!(
+            "{} -> {}",
+            compact(&turn.user),
+            compact(&turn.assistant)
+        ));
+        if self.summary.len() > SUMMARY_LINES {
+            self.summary.pop_front();
+        }
+    }
+
+    fn summary_text(&self) -> Option<String> {
+        if self.summary.is_empty() {
+            return None;
+        }
+        let lines: Vec<&str> = self.summary.iter().map(String::as_str).collect();
+        Some(format!(
+            "Summary of {} earlier requests and answers, the last {} of them:\n{}",
+            self.folded,
+            lines.len(),
+            lines.join("\n")
+        ))
+    }
+}
+
+/// Rough token count, about four characters per token for English text and JSON
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Context size of the model: the number at the end of names like llama3-8b-8192,
+/// otherwise the given default of the provider
+pub fn context_limit(model: &str, default: usize) -> usize {
+    model
+        .rsplit('-')
+        .next()
+        .and_then(|size| size.parse::<usize>().ok())
+        .filter(|size| *size >= 1024)
+        .unwrap_or(default)
+}
+
+fn pair_tokens(user: &str, assistant: &str) -> usize {
+    estimate_tokens(user) + estimate_tokens(assistant) + 2 * MESSAGE_OVERHEAD
+}
+
+fn message(role: Role, content: String) -> Message {
+    Message {
+        role: role.as_string(),
+        content,
+    }
+}
+
+/// the message without whitespace, so it takes one line of the summary
+fn compact(text: &str) -> String {
+    text.split_whitespace().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    fn conversation(policy: HistoryPolicy, context_tokens: usize) -> Conversation {
+        let mut conversation = Conversation::new("system", context_tokens);
+        conversation.set_policy(policy);
+        for i in 0..5 {
+            conversation.record(format!("request {}", i), format!("answer {}", i));
+        }
+        conversation
+    }
+
+    #[rstest]
+    #[case(HistoryPolicy::Stateless, 2)]
+    #[case(HistoryPolicy::Window { turns: 2 }, 6)]
+    #[case(HistoryPolicy::Window { turns: 10 }, 12)]
+    #[case(HistoryPolicy::Summary { turns: 2 }, 6)]
+    fn test_policy(#[case] policy: HistoryPolicy, #[case] messages: usize) {
+        let conversation = conversation(policy, 8192);
+
+        let sent = conversation.messages("request 5");
+
+        assert_eq!(sent.len(), messages);
+        assert_eq!(sent.last().unwrap().content, "request 5");
+    }
+
+    #[test]
+    fn test_summary() {
+        let conversation = conversation(HistoryPolicy::Summary { turns: 2 }, 8192);
+
+        let sent = conversation.messages("request 5");
+
+        assert!(sent[0].content.contains("Summary of 3 earlier"));
+        assert!(sent[0].content.contains("request2 -> answer2"));
+        assert_eq!(sent[1].content, "request 3");
+    }
+
+    #[test]
+    fn test_context_limit_drops_oldest_turns() {
+        // room for the system prompt, the request and one turn
+        let conversation = conversation(HistoryPolicy::Window { turns: 5 }, ANSWER_TOKENS + 30);
+
+        let sent = conversation.messages("request 5");
+
+        assert_eq!(sent.len(), 4);
+        assert_eq!(sent[1].content, "request 4");
+    }
+
+    #[rstest]
+    #[case("llama3-70b-8192", 8192)]
+    #[case("Mixtral-8x7b-32768", 32768)]
+    #[case("phi3", 2048)]
+    #[case("gemma-7b-it", 2048)]
+    fn test_context_limit(#[case] model: &str, #[case] tokens: usize) {
+        assert_eq!(context_limit(model, 2048), tokens);
+    }
+}
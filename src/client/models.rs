@@ -1,7 +1,9 @@
-use crate::models::Direction;
+use crate::models::{Direction, Point};
 use core::str;
 
 use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use strum::IntoEnumIterator;
 
 pub static SYSTEM_PROMPT: &str = r#" 
 Input: Take coordinates of the snake head and food in JSON format:
@@ -19,6 +21,49 @@ For example if food_y is 41, and snake_head_y is 20, snake needs to go up 21 tim
 Remember: Your goal is to make "snake_head_x" equal to "food_x" and "snake_head_y" equal to "food_y".
     Make sure to handle cases where the food is directly above, below, to the left or right of the snake head.
 "#;
+pub static COMPACT_SYSTEM_PROMPT: &str = r#"
+You control a snake on a grid, "up" increases y, "right" increases x.
+Input: {"snake_head_x": int, "snake_head_y": int, "food_x": int, "food_y": int}
+Answer only with JSON: {"commands": [{"command": "up" | "down" | "left" | "right", "repeat": int}]}
+"repeat" is the number of cells to move, so after all commands the snake head must be at the food.
+"#;
+
+pub static TOOLS_SYSTEM_PROMPT: &str = r#"
+You control a snake on a grid, "up" increases y, "right" increases x, (0, 0) is the bottom left corner.
+The user message has the coordinates of the snake head and the food.
+Use the tools: get_state() and look() to inspect the board, move(direction, steps) to plan the moves.
+Reach the food without hitting the walls or the snake body, call move for every part of the path.
+"#;
+
+/// system prompt used by a profile
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PromptVariant {
+    #[default]
+    Default,
+    Compact,
+}
+
+impl PromptVariant {
+    pub fn system_prompt(&self) -> &'static str {
+        match self {
+            PromptVariant::Default => SYSTEM_PROMPT,
+            PromptVariant::Compact => COMPACT_SYSTEM_PROMPT,
+        }
+    }
+}
+
+/// how the client asks the model for commands
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    /// JSON with the commands in the message content
+    #[default]
+    Json,
+    /// move/look/get_state tool calls
+    Tools,
+}
+
 #[allow(dead_code)]
 pub enum Role {
     User,
@@ -41,13 +86,13 @@ pub struct Choice {
     pub message: Message,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Message {
     pub role: String,
     pub content: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct InputContent {
     pub snake_head_x: i32,
     pub snake_head_y: i32,
@@ -55,13 +100,68 @@ pub struct InputContent {
     pub food_y: i32,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// board around the snake, sent to the clients that can inspect it
+#[derive(Debug, Clone, Default)]
+pub struct BoardView {
+    pub width: i32,
+    pub height: i32,
+    /// cells taken by the snakes, heads included
+    pub occupied: Vec<Point>,
+    /// cells of the snake of the client from the head to the tail, they are in `occupied` too.
+    /// Empty when only the head is known
+    pub snake: Vec<Point>,
+    /// direction the snake is moving in
+    pub heading: Option<Direction>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct OutputContent {
     pub commands: Vec<Commands>,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct Commands {
     pub command: Direction,
     pub repeat: i32,
 }
+
+impl OutputContent {
+    /// JSON schema of the answer, sent to the providers that support structured output
+    pub fn json_schema() -> Value {
+        let directions: Vec<Value> = Direction::iter()
+            .map(|d| serde_json::to_value(d).unwrap())
+            .collect();
+        json!({
+            "type": "object",
+            "properties": {
+                "commands": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "command": { "type": "string", "enum": directions },
+                            "repeat": { "type": "integer" }
+                        },
+                        "required": ["command", "repeat"],
+                        "additionalProperties": false
+                    }
+                }
+            },
+            "required": ["commands"],
+            "additionalProperties": false
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_schema_lists_directions() {
+        let schema = OutputContent::json_schema();
+        let command = &schema["properties"]["commands"]["items"]["properties"]["command"];
+
+        assert_eq!(command["enum"], json!(["up", "down", "left", "right"]));
+    }
+}
@@ -1,118 +1,144 @@
 use std::time::Duration;
 
-use reqwest::{
-    blocking::{Client, Response},
-    StatusCode,
-};
+use log::warn;
+use reqwest::{Client, RequestBuilder};
 use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
 
 use super::{
-    models::{self, InputContent, Message, OutputContent, Role, SYSTEM_PROMPT},
-    ApiClient,
+    chat::{self, ChatApi},
+    error::ClientError,
+    http::BodyReader,
+    models::{self, Message, OutputContent},
+    stream::{self, Streamed},
+    usage::Tokens,
 };
 
+/// default num_ctx of ollama
+const DEFAULT_CONTEXT_TOKENS: usize = 2048;
+
 #[derive(Serialize, Deserialize, Debug)]
 struct OllamaRequest {
     model: String,
     messages: Vec<models::Message>,
     stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<OllamaOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct OllamaOptions {
+    temperature: f32,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct OllamaResponse {
     message: models::Message,
+    prompt_eval_count: Option<u64>,
+    eval_count: Option<u64>,
 }
 
-pub struct OllamaClient {
-    client: Client,
-    url: String,
-    request: OllamaRequest,
+impl OllamaResponse {
+    fn tokens(&self) -> Option<Tokens> {
+        Some(Tokens {
+            prompt_tokens: self.prompt_eval_count?,
+            completion_tokens: self.eval_count?,
+        })
+    }
 }
 
-impl ApiClient for OllamaClient {
-    fn snake_commands(&mut self, input: InputContent) -> Result<OutputContent, String> {
-        self.snake_commands(input)
-    }
+/// Chat API of Ollama
+pub struct OllamaApi {
+    url: String,
+    model: String,
+    timeout: Duration,
+    options: Option<OllamaOptions>,
+    format: Option<Value>,
 }
 
-impl OllamaClient {
+impl OllamaApi {
     pub fn new(url: String, model: String) -> Self {
         Self {
-            client: Client::new(),
             url,
-            request: OllamaRequest {
-                model: model,
-                messages: vec![Message {
-                    role: Role::System.as_string(),
-                    content: SYSTEM_PROMPT.to_string(),
-                }],
-                stream: false,
-            },
+            model,
+            timeout: Duration::from_secs(60 * 10),
+            options: None,
+            format: Some(structured_output()),
         }
     }
+}
 
-    pub fn snake_commands(&mut self, input: InputContent) -> Result<OutputContent, String> {
-        if self.request.messages.len() > 1 {
-            self.request.messages.pop();
-        }
-        self.request.messages.push(models::Message {
-            role: Role::User.as_string(),
-            content: serde_json::to_string(&input).unwrap(),
-        });
-
-        let body = match serde_json::to_string(&self.request) {
-            Ok(body) => body,
-            Err(e) => return Err(format!("Failed to serialize request: {}", e)),
-        };
+impl ChatApi for OllamaApi {
+    const CONTEXT_TOKENS: usize = DEFAULT_CONTEXT_TOKENS;
 
-        let resp = self.post(&self.url, body);
-        let status = resp.status();
+    fn model(&self) -> &str {
+        &self.model
+    }
 
-        if status != StatusCode::OK {
-            return Err(format!("Request failed with status: {}", status));
-        }
+    fn set_temperature(&mut self, temperature: f32) {
+        self.options = Some(OllamaOptions { temperature });
+    }
 
-        let resp_body = match resp.text() {
-            Ok(body) => body,
-            Err(e) => return Err(format!("Failed to get response body: {}", e)),
-        };
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    fn set_structured_output(&mut self, enabled: bool) {
+        self.format = enabled.then(structured_output);
+    }
 
-        let resp: Option<OllamaResponse> = match serde_json::from_str(&resp_body) {
+    fn request(
+        &self,
+        client: &Client,
+        messages: &[Message],
+        stream: bool,
+    ) -> Result<RequestBuilder, String> {
+        let body = chat::body(&OllamaRequest {
+            model: self.model.clone(),
+            messages: messages.to_vec(),
+            stream,
+            options: self.options.clone(),
+            format: self.format.clone(),
+        })?;
+        Ok(client.post(&self.url).timeout(self.timeout).body(body))
+    }
+
+    fn read_response(&self, body: &str) -> Result<(String, Option<Tokens>), String> {
+        let resp: Option<OllamaResponse> = match serde_json::from_str(body) {
             Ok(r) => r,
             Err(e) => {
                 return Err(format!("Failed to parse response body {}", e));
             }
         };
+        match resp {
+            Some(resp) => Ok((resp.message.content.clone(), resp.tokens())),
+            None => Err("No response form api".to_string()),
+        }
+    }
 
-        if let Some(resp) = resp {
-            let json = extract_json(&resp.message.content);
-            let _res: OutputContent = match serde_json::from_str(&json) {
-                Ok(res) => return Ok(res),
-                Err(e) => {
-                    return Err(format!(
-                        "Failed to parse messages body: {}, response: {}",
-                        e, json
-                    ));
-                }
-            };
-        } else {
-            return Err("No response form api".to_string());
-        };
+    fn read_stream(
+        &self,
+        body: BodyReader,
+        on_chunk: &mut dyn FnMut(&str),
+    ) -> Result<Streamed, ClientError> {
+        stream::read_ndjson(body, on_chunk)
     }
 
-    fn post(&self, url: &str, body: String) -> Response {
-        self.client
-            .post(url)
-            .timeout(Duration::from_secs(60 * 10))
-            .body(body)
-            .send()
-            .unwrap()
+    fn drop_structured_output(&mut self, error: &str) -> bool {
+        if self.format.is_none() || !chat::mentions(error, &["format"]) {
+            return false;
+        }
+        warn!(
+            "Structured output is not supported by {}, falling back to the prompt",
+            self.url
+        );
+        self.format = None;
+        true
     }
 }
 
-fn extract_json(input: &str) -> String {
-    let start_idx = input.find('{').unwrap_or(input.len());
-    let end_idx = input.rfind('}').unwrap_or(input.len());
-    let extracted = input[start_idx..end_idx + 1].to_string();
-    extracted
+fn structured_output() -> Value {
+    OutputContent::json_schema()
 }
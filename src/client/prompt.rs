@@ -0,0 +1,167 @@
+use std::path::Path;
+
+use figment::{
+    providers::{Format, Yaml},
+    Figment,
+};
+use serde_derive::Deserialize;
+
+use super::models::{BoardView, InputContent, PromptVariant};
+use crate::models::Direction;
+
+/// user message of the built-in templates, the JSON of InputContent
+const DEFAULT_USER_TEMPLATE: &str = r#"{"snake_head_x":{{snake_head_x}},"snake_head_y":{{snake_head_y}},"food_x":{{food_x}},"food_y":{{food_y}}}"#;
+
+/// placeholders that can be used in the user template
+const PLACEHOLDERS: [&str; 7] = [
+    "snake_head_x",
+    "snake_head_y",
+    "food_x",
+    "food_y",
+    "width",
+    "height",
+    "direction",
+];
+
+/// example question and answer sent before the real request
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Example {
+    pub user: String,
+    pub assistant: String,
+}
+
+/// System prompt, user message with `{{field}}` placeholders and few-shot examples,
+/// either built-in or loaded from a YAML file
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub system: String,
+    #[serde(default = "default_user_template")]
+    pub user: String,
+    #[serde(default)]
+    pub examples: Vec<Example>,
+}
+
+impl PromptTemplate {
+    pub fn builtin(variant: &PromptVariant) -> Self {
+        let name = match variant {
+            PromptVariant::Default => "default",
+            PromptVariant::Compact => "compact",
+        };
+        Self {
+            name: name.to_string(),
+            system: variant.system_prompt().to_string(),
+            user: default_user_template(),
+            examples: Vec::new(),
+        }
+    }
+
+    /// Reads the template file and checks it by rendering a sample state
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Err(format!("prompt file {} not found", path.display()));
+        }
+        let template: PromptTemplate = Figment::from(Yaml::file(path))
+            .extract()
+            .map_err(|e| format!("invalid prompt file {}: {}", path.display(), e))?;
+        template.validate()?;
+        Ok(template)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.system.trim().is_empty() {
+            return Err(format!("prompt {}: system prompt is empty", self.name));
+        }
+        let sample = InputContent {
+            snake_head_x: 30,
+            snake_head_y: 20,
+            food_x: 10,
+            food_y: 5,
+        };
+        let board = BoardView {
+            width: 60,
+            height: 40,
+            heading: Some(Direction::Up),
+            ..Default::default()
+        };
+        let rendered = self.render_user(&sample, &board);
+        if let Some(start) = rendered.find("{{") {
+            let end = rendered[start..]
+                .find("}}")
+                .map_or(rendered.len(), |e| start + e + 2);
+            return Err(format!(
+                "prompt {}: unknown placeholder {}, known ones are {}",
+                self.name,
+                &rendered[start..end],
+                PLACEHOLDERS.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn render_user(&self, input: &InputContent, board: &BoardView) -> String {
+        let direction = board
+            .heading
+            .as_ref()
+            .and_then(|d| serde_json::to_value(d).ok())
+            .and_then(|d| d.as_str().map(str::to_string))
+            .unwrap_or_else(|| "unknown".to_string());
+        let values = [
+            input.snake_head_x.to_string(),
+            input.snake_head_y.to_string(),
+            input.food_x.to_string(),
+            input.food_y.to_string(),
+            board.width.to_string(),
+            board.height.to_string(),
+            direction,
+        ];
+
+        PLACEHOLDERS
+            .iter()
+            .zip(values)
+            .fold(self.user.clone(), |text, (name, value)| {
+                text.replace(&format!("{{{{{}}}}}", name), &value)
+            })
+    }
+}
+
+fn default_user_template() -> String {
+    DEFAULT_USER_TEMPLATE.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[test]
+    fn test_builtin_user_message_is_input_json() {
+        let input = InputContent {
+            snake_head_x: 10,
+            snake_head_y: 20,
+            food_x: 10,
+            food_y: 53,
+        };
+        let template = PromptTemplate::builtin(&PromptVariant::Default);
+
+        let user = template.render_user(&input, &BoardView::default());
+
+        assert_eq!(user, serde_json::to_string(&input).unwrap());
+    }
+
+    #[rstest]
+    #[case("head {{snake_head_x}},{{snake_head_y}} on {{width}}x{{height}}", true)]
+    #[case("going {{direction}}", true)]
+    #[case("food at {{food}}", false)]
+    fn test_validate(#[case] user: &str, #[case] valid: bool) {
+        let template = PromptTemplate {
+            name: "test".to_string(),
+            system: "system".to_string(),
+            user: user.to_string(),
+            examples: Vec::new(),
+        };
+
+        assert_eq!(template.validate().is_ok(), valid);
+    }
+}
@@ -0,0 +1,84 @@
+const OPEN: &str = "<think>";
+const CLOSE: &str = "</think>";
+
+/// Separates the reasoning of the model from its answer.
+/// The reasoning is the `<think>` block of reasoning models, the answer is the text without it.
+/// Without the block the text written before the JSON counts as the reasoning
+/// and the answer is left as it is
+pub fn split(text: &str) -> (Option<String>, String) {
+    let open = text.find(OPEN);
+    let close = text.find(CLOSE);
+    let (reasoning, answer) = match (open, close) {
+        (Some(open), Some(close)) if open < close => (
+            &text[open + OPEN.len()..close],
+            format!("{}{}", &text[..open], &text[close + CLOSE.len()..]),
+        ),
+        // the opening tag is part of the chat template of some models
+        (None, Some(close)) => (&text[..close], text[close + CLOSE.len()..].to_string()),
+        // cut off while thinking, there is no answer
+        (Some(open), _) => (&text[open + OPEN.len()..], String::new()),
+        (None, None) => (&text[..json_start(text)], text.to_string()),
+    };
+    let reasoning = reasoning.trim();
+    let reasoning = (!reasoning.is_empty()).then(|| reasoning.to_string());
+    (reasoning, answer.trim().to_string())
+}
+
+/// Where the answer can start in a text that is still being streamed,
+/// None while the model is inside the `<think>` block
+pub fn answer_start(text: &str) -> Option<usize> {
+    match (text.find(OPEN), text.find(CLOSE)) {
+        (_, Some(close)) => Some(close + CLOSE.len()),
+        (Some(_), None) => None,
+        (None, None) => Some(0),
+    }
+}
+
+/// start of the code fence or of the JSON object
+fn json_start(text: &str) -> usize {
+    [text.find("```"), text.find('{')]
+        .into_iter()
+        .flatten()
+        .min()
+        .unwrap_or(text.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[case(r#"{"commands": []}"#, None, r#"{"commands": []}"#)]
+    #[case(
+        "<think>The food is {above}, go up.</think>\n{\"commands\": []}",
+        Some("The food is {above}, go up."),
+        r#"{"commands": []}"#
+    )]
+    #[case(
+        "The food is above.\n</think>\n\n{\"commands\": []}",
+        Some("The food is above."),
+        r#"{"commands": []}"#
+    )]
+    #[case("<think>The food is", Some("The food is"), "")]
+    #[case(
+        "The food is above, so: {\"commands\": []}",
+        Some("The food is above, so:"),
+        "The food is above, so: {\"commands\": []}"
+    )]
+    fn test_split(#[case] text: &str, #[case] reasoning: Option<&str>, #[case] answer: &str) {
+        let (found, rest) = split(text);
+
+        assert_eq!(found.as_deref(), reasoning);
+        assert_eq!(rest, answer);
+    }
+
+    #[rstest]
+    #[case("{\"commands\"", Some(0))]
+    #[case("<think>{\"commands\"", None)]
+    #[case("<think>up</think>{", Some(17))]
+    fn test_answer_start(#[case] text: &str, #[case] start: Option<usize>) {
+        assert_eq!(answer_start(text), start);
+    }
+}
@@ -0,0 +1,303 @@
+use log::warn;
+use serde_json::Value;
+
+use super::models::{BoardView, Commands, OutputContent};
+use crate::models::Direction;
+
+/// answer of the model after the repairs
+#[derive(Debug)]
+pub struct Repaired {
+    pub output: OutputContent,
+    /// what was changed or dropped, empty when the answer was fine
+    pub repairs: Vec<String>,
+}
+
+/// Parses the answer of the model and logs what had to be repaired
+pub fn read_output(text: &str, board: &BoardView) -> Result<OutputContent, String> {
+    let repaired = parse_output(text, board)?;
+    if !repaired.repairs.is_empty() {
+        warn!("Repaired the answer: {}", repaired.repairs.join("; "));
+    }
+    Ok(repaired.output)
+}
+
+/// Tolerant parser of `{"commands": [...]}`, accepts code fences, text around the JSON,
+/// direction synonyms, string or negative repeats, and drops the commands that can't be used
+pub fn parse_output(text: &str, board: &BoardView) -> Result<Repaired, String> {
+    let mut repairs = Vec::new();
+
+    let mut json = text.trim();
+    if let Some(inner) = strip_code_fence(json) {
+        repairs.push("removed the code fence".to_string());
+        json = inner.trim();
+    }
+    let (Some(start), Some(end)) = (json.find('{'), json.rfind('}')) else {
+        return Err(format!("No JSON in the answer: {}", text));
+    };
+    if start > end {
+        return Err(format!("No JSON in the answer: {}", text));
+    }
+    if start > 0 || end < json.len() - 1 {
+        repairs.push("removed the text around the JSON".to_string());
+    }
+    let value: Value = serde_json::from_str(&json[start..=end])
+        .map_err(|e| format!("Failed to parse messages body: {}, response: {}", e, text))?;
+
+    let entries = match &value["commands"] {
+        Value::Array(entries) => entries.clone(),
+        _ if value.get("command").is_some() => {
+            repairs.push("read a single command without the commands array".to_string());
+            vec![value.clone()]
+        }
+        _ => return Err(format!("No commands in the answer: {}", text)),
+    };
+
+    let mut repairer = Repairer::new(board);
+    let commands: Vec<Commands> = entries.iter().filter_map(|e| repairer.command(e)).collect();
+    repairs.append(&mut repairer.repairs);
+
+    if commands.is_empty() {
+        return Err(format!(
+            "No usable commands in the answer: {}, {}",
+            text,
+            repairs.join("; ")
+        ));
+    }
+    Ok(Repaired {
+        output: OutputContent { commands },
+        repairs,
+    })
+}
+
+/// Checks the commands one by one, remembers the direction of the previous one
+/// to catch the reversals into the body
+pub struct Repairer {
+    width: i32,
+    height: i32,
+    heading: Option<Direction>,
+    pub repairs: Vec<String>,
+}
+
+impl Repairer {
+    pub fn new(board: &BoardView) -> Self {
+        Self {
+            width: board.width,
+            height: board.height,
+            heading: board.heading.clone(),
+            repairs: Vec::new(),
+        }
+    }
+
+    /// one entry of the commands array, None when it can't be used
+    pub fn command(&mut self, entry: &Value) -> Option<Commands> {
+        let Some(raw) = entry.get("command").and_then(Value::as_str) else {
+            self.repairs.push(format!("dropped {}: no command", entry));
+            return None;
+        };
+        let Some(mut direction) = parse_direction(raw) else {
+            self.repairs
+                .push(format!("dropped {}: unknown command {:?}", entry, raw));
+            return None;
+        };
+        if direction_name(&direction) != raw {
+            self.repairs.push(format!(
+                "command {:?} read as {}",
+                raw,
+                direction_name(&direction)
+            ));
+        }
+
+        let mut repeat = match entry.get("repeat") {
+            None => {
+                self.repairs
+                    .push(format!("repeat of {} is missing, used 1", raw));
+                1
+            }
+            Some(Value::Number(n)) => match n.as_i64() {
+                Some(repeat) => repeat,
+                None => {
+                    let repeat = n.as_f64().unwrap_or_default().round() as i64;
+                    self.repairs
+                        .push(format!("repeat {} rounded to {}", n, repeat));
+                    repeat
+                }
+            },
+            Some(Value::String(s)) => match s.trim().parse::<i64>() {
+                Ok(repeat) => {
+                    self.repairs
+                        .push(format!("repeat {:?} read as {}", s, repeat));
+                    repeat
+                }
+                Err(_) => {
+                    self.repairs
+                        .push(format!("dropped {}: repeat {:?} is not a number", raw, s));
+                    return None;
+                }
+            },
+            Some(other) => {
+                self.repairs
+                    .push(format!("dropped {}: repeat {} is not a number", raw, other));
+                return None;
+            }
+        };
+
+        if repeat < 0 {
+            let opposite = direction.opposite();
+            self.repairs.push(format!(
+                "{} {} turned into {} {}",
+                direction_name(&direction),
+                repeat,
+                direction_name(&opposite),
+                repeat.saturating_neg()
+            ));
+            direction = opposite;
+            repeat = repeat.saturating_neg();
+        }
+        if repeat == 0 {
+            self.repairs.push(format!(
+                "dropped {}: repeat is 0",
+                direction_name(&direction)
+            ));
+            return None;
+        }
+        let limit = self.limit(&direction);
+        if repeat > limit {
+            self.repairs.push(format!(
+                "repeat {} of {} clamped to {}",
+                repeat,
+                direction_name(&direction),
+                limit
+            ));
+            repeat = limit;
+        }
+        if self.heading.as_ref() == Some(&direction.opposite()) {
+            self.repairs.push(format!(
+                "dropped {} {}: reverses into the body",
+                direction_name(&direction),
+                repeat
+            ));
+            return None;
+        }
+
+        self.heading = Some(direction.clone());
+        Some(Commands {
+            command: direction,
+            repeat: repeat as i32,
+        })
+    }
+
+    /// the longest move that stays on the board, the largest repeat when the board size is unknown
+    fn limit(&self, direction: &Direction) -> i64 {
+        let size = match direction {
+            Direction::Up | Direction::Down => self.height,
+            Direction::Left | Direction::Right => self.width,
+        };
+        if size > 0 {
+            (size - 1).max(1) as i64
+        } else {
+            i32::MAX as i64
+        }
+    }
+}
+
+fn parse_direction(raw: &str) -> Option<Direction> {
+    match raw.trim().to_lowercase().as_str() {
+        "up" | "u" | "north" | "n" => Some(Direction::Up),
+        "down" | "d" | "south" | "s" => Some(Direction::Down),
+        "left" | "l" | "west" | "w" => Some(Direction::Left),
+        "right" | "r" | "east" | "e" => Some(Direction::Right),
+        _ => None,
+    }
+}
+
+/// the direction as the model writes it, e.g. up
+pub fn direction_name(direction: &Direction) -> String {
+    serde_json::to_value(direction)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// text inside ```json ... ```, None when there is no fence
+fn strip_code_fence(text: &str) -> Option<&str> {
+    let start = text.find("```")?;
+    let after = &text[start + 3..];
+    // skip the language tag
+    let body = &after[after.find('\n')? + 1..];
+    let end = body.find("```").unwrap_or(body.len());
+    Some(&body[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    fn board() -> BoardView {
+        BoardView {
+            width: 60,
+            height: 40,
+            heading: Some(Direction::Right),
+            ..Default::default()
+        }
+    }
+
+    fn command(command: Direction, repeat: i32) -> Commands {
+        Commands { command, repeat }
+    }
+
+    #[rstest]
+    #[case(r#"{"commands": [{"command": "up", "repeat": 3}]}"#, vec![command(Direction::Up, 3)], 0)]
+    #[case("```json\n{\"commands\": [{\"command\": \"up\", \"repeat\": 3}]}\n```", vec![command(Direction::Up, 3)], 1)]
+    #[case(r#"Sure! {"commands": [{"command": "up", "repeat": 3}]} Good luck"#, vec![command(Direction::Up, 3)], 1)]
+    #[case(r#"{"commands": [{"command": "North", "repeat": "3"}]}"#, vec![command(Direction::Up, 3)], 2)]
+    #[case(r#"{"commands": [{"command": "up", "repeat": -3}]}"#, vec![command(Direction::Down, 3)], 1)]
+    #[case(r#"{"commands": [{"command": "up", "repeat": 100}]}"#, vec![command(Direction::Up, 39)], 1)]
+    #[case(r#"{"command": "up", "repeat": 3}"#, vec![command(Direction::Up, 3)], 1)]
+    #[case(
+        r#"{"commands": [{"command": "left", "repeat": 3}, {"command": "jump", "repeat": 1}, {"command": "up", "repeat": 2}]}"#,
+        vec![command(Direction::Up, 2)],
+        2
+    )]
+    #[case(
+        r#"{"commands": [{"command": "up", "repeat": 3}, {"command": "down", "repeat": 2}, {"command": "left", "repeat": 0}]}"#,
+        vec![command(Direction::Up, 3)],
+        2
+    )]
+    fn test_parse_output(
+        #[case] text: &str,
+        #[case] commands: Vec<Commands>,
+        #[case] repairs: usize,
+    ) {
+        let repaired = parse_output(text, &board()).unwrap();
+
+        assert_eq!(repaired.output.commands, commands);
+        assert_eq!(repaired.repairs.len(), repairs, "{:?}", repaired.repairs);
+    }
+
+    #[rstest]
+    #[case(r#"{"commands": [{"command": "up", "repeat": 3}]}"#, 3)]
+    #[case(
+        r#"{"commands": [{"command": "up", "repeat": 10000000000}]}"#,
+        i32::MAX
+    )]
+    #[case(
+        r#"{"commands": [{"command": "up", "repeat": -9223372036854775808}]}"#,
+        i32::MAX
+    )]
+    fn test_parse_output_unknown_board(#[case] text: &str, #[case] repeat: i32) {
+        let repaired = parse_output(text, &BoardView::default()).unwrap();
+
+        assert_eq!(repaired.output.commands[0].repeat, repeat);
+    }
+
+    #[rstest]
+    #[case("I don't know")]
+    #[case(r#"{"commands": []}"#)]
+    #[case(r#"{"commands": [{"command": "left", "repeat": 3}]}"#)]
+    #[case(r#"{"moves": "up"}"#)]
+    fn test_parse_output_fails(#[case] text: &str) {
+        assert!(parse_output(text, &board()).is_err());
+    }
+}
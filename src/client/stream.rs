@@ -0,0 +1,465 @@
+use std::io::{BufRead, BufReader, Read};
+
+use serde_derive::Deserialize;
+use serde_json::Value;
+
+use super::{
+    error::ClientError,
+    models::{BoardView, Commands, Message},
+    reasoning,
+    repair::Repairer,
+    usage::Tokens,
+};
+
+/// chunk of an OpenAI compatible server-sent events stream
+#[derive(Deserialize, Debug)]
+struct SseChunk {
+    #[serde(default)]
+    choices: Vec<SseChoice>,
+    /// sent in the last chunk by OpenAI
+    usage: Option<Tokens>,
+    /// groq sends the usage here
+    x_groq: Option<XGroq>,
+}
+
+#[derive(Deserialize, Debug)]
+struct XGroq {
+    usage: Option<Tokens>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SseChoice {
+    delta: SseDelta,
+}
+
+#[derive(Deserialize, Debug)]
+struct SseDelta {
+    content: Option<String>,
+}
+
+/// line of an Ollama newline-delimited JSON stream
+#[derive(Deserialize, Debug)]
+struct NdjsonChunk {
+    message: Option<Message>,
+    #[serde(default)]
+    done: bool,
+    prompt_eval_count: Option<u64>,
+    eval_count: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct CompletionChunk {
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    stop: bool,
+    tokens_evaluated: Option<u64>,
+    tokens_predicted: Option<u64>,
+}
+
+/// event of an Anthropic Messages stream, only the fields the answer needs
+#[derive(Deserialize)]
+struct MessagesEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    /// sent with message_start, holds the input tokens
+    message: Option<MessagesStart>,
+    /// text of a content_block_delta
+    delta: Option<MessagesDelta>,
+    /// sent with message_delta, holds the output tokens
+    usage: Option<MessagesUsage>,
+    error: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct MessagesStart {
+    usage: Option<MessagesUsage>,
+}
+
+#[derive(Deserialize)]
+struct MessagesDelta {
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MessagesUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+}
+
+/// whole content of a streamed answer
+pub struct Streamed {
+    pub content: String,
+    /// usage of the call when the server reported it
+    pub tokens: Option<Tokens>,
+}
+
+/// Reads an OpenAI compatible `data: {...}` stream until `data: [DONE]`,
+/// passes every piece of the message content to `on_chunk` and returns the whole content
+pub fn read_sse(
+    reader: impl Read,
+    mut on_chunk: impl FnMut(&str),
+) -> Result<Streamed, ClientError> {
+    let mut content = String::new();
+    let mut tokens = None;
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        let Some(data) = line.strip_prefix("data:") else {
+            continue;
+        };
+        let data = data.trim();
+        if data == "[DONE]" {
+            break;
+        }
+        let chunk: SseChunk = serde_json::from_str(data)
+            .map_err(|e| format!("Failed to parse stream chunk {}: {}", e, data))?;
+        if let Some(piece) = chunk.choices.first().and_then(|c| c.delta.content.as_ref()) {
+            content.push_str(piece);
+            on_chunk(piece);
+        }
+        if let Some(usage) = chunk.usage.or(chunk.x_groq.and_then(|x| x.usage)) {
+            tokens = Some(usage);
+        }
+    }
+    Ok(Streamed { content, tokens })
+}
+
+/// Reads an Ollama stream, one JSON object per line, until the `done` one,
+/// passes every piece of the message content to `on_chunk` and returns the whole content
+pub fn read_ndjson(
+    reader: impl Read,
+    mut on_chunk: impl FnMut(&str),
+) -> Result<Streamed, ClientError> {
+    let mut content = String::new();
+    let mut tokens = None;
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let chunk: NdjsonChunk = serde_json::from_str(&line)
+            .map_err(|e| format!("Failed to parse stream chunk {}: {}", e, line))?;
+        if let Some(message) = chunk.message {
+            content.push_str(&message.content);
+            on_chunk(&message.content);
+        }
+        if chunk.done {
+            if let (Some(prompt), Some(completion)) = (chunk.prompt_eval_count, chunk.eval_count) {
+                tokens = Some(Tokens {
+                    prompt_tokens: prompt,
+                    completion_tokens: completion,
+                });
+            }
+            break;
+        }
+    }
+    Ok(Streamed { content, tokens })
+}
+
+/// Reads a llama.cpp `/completion` stream of `data: {...}` lines until the `stop` one,
+/// passes every piece of the content to `on_chunk` and returns the whole content
+pub fn read_completion_sse(
+    reader: impl Read,
+    mut on_chunk: impl FnMut(&str),
+) -> Result<Streamed, ClientError> {
+    let mut content = String::new();
+    let mut tokens = None;
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        let Some(data) = line.strip_prefix("data:") else {
+            continue;
+        };
+        let chunk: CompletionChunk = serde_json::from_str(data.trim())
+            .map_err(|e| format!("Failed to parse stream chunk {}: {}", e, data))?;
+        if !chunk.content.is_empty() {
+            content.push_str(&chunk.content);
+            on_chunk(&chunk.content);
+        }
+        if chunk.stop {
+            if let (Some(prompt), Some(completion)) =
+                (chunk.tokens_evaluated, chunk.tokens_predicted)
+            {
+                tokens = Some(Tokens {
+                    prompt_tokens: prompt,
+                    completion_tokens: completion,
+                });
+            }
+            break;
+        }
+    }
+    Ok(Streamed { content, tokens })
+}
+
+/// Reads an Anthropic Messages stream until `message_stop`,
+/// passes the text of every content block delta to `on_chunk` and returns the whole content
+pub fn read_messages_sse(
+    reader: impl Read,
+    mut on_chunk: impl FnMut(&str),
+) -> Result<Streamed, ClientError> {
+    let mut content = String::new();
+    let mut input_tokens = None;
+    let mut output_tokens = None;
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        let Some(data) = line.strip_prefix("data:") else {
+            continue;
+        };
+        let event: MessagesEvent = serde_json::from_str(data.trim())
+            .map_err(|e| format!("Failed to parse stream chunk {}: {}", e, data))?;
+        match event.kind.as_str() {
+            "message_start" => {
+                let usage = event.message.and_then(|m| m.usage);
+                input_tokens = usage.map(|u| u.input_tokens);
+            }
+            "content_block_delta" => {
+                if let Some(text) = event.delta.and_then(|d| d.text) {
+                    content.push_str(&text);
+                    on_chunk(&text);
+                }
+            }
+            "message_delta" => output_tokens = event.usage.map(|u| u.output_tokens),
+            "message_stop" => break,
+            "error" => {
+                let error = event.error.unwrap_or_default();
+                return Err(ClientError::Network(format!(
+                    "Failed to read stream: {}",
+                    error["message"]
+                )));
+            }
+            _ => {}
+        }
+    }
+    let tokens = match (input_tokens, output_tokens) {
+        (Some(prompt), Some(completion)) => Some(Tokens {
+            prompt_tokens: prompt,
+            completion_tokens: completion,
+        }),
+        _ => None,
+    };
+    Ok(Streamed { content, tokens })
+}
+
+#[derive(PartialEq)]
+enum ParserState {
+    /// looking for the array after the "commands" key
+    Seeking,
+    InArray,
+    Done,
+}
+
+/// Incremental parser of `{"commands": [...]}` that releases every entry of the array
+/// as soon as its closing brace arrives
+pub struct CommandStreamParser {
+    repairer: Repairer,
+    buffer: String,
+    state: ParserState,
+    position: usize,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    entry_start: usize,
+}
+
+impl CommandStreamParser {
+    pub fn new(board: &BoardView) -> Self {
+        Self {
+            repairer: Repairer::new(board),
+            buffer: String::new(),
+            state: ParserState::Seeking,
+            position: 0,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+            entry_start: 0,
+        }
+    }
+
+    /// adds the next piece of the text and returns the entries completed by it
+    pub fn push(&mut self, chunk: &str) -> Vec<Commands> {
+        self.buffer.push_str(chunk);
+        let mut commands = Vec::new();
+
+        if self.state == ParserState::Seeking {
+            // the reasoning of the model can mention the key too
+            let Some(start) = reasoning::answer_start(&self.buffer) else {
+                return commands;
+            };
+            let Some(key) = self.buffer[start..].find("\"commands\"") else {
+                return commands;
+            };
+            let key = start + key;
+            let Some(bracket) = self.buffer[key..].find('[') else {
+                return commands;
+            };
+            self.position = key + bracket + 1;
+            self.state = ParserState::InArray;
+        }
+
+        while self.state == ParserState::InArray && self.position < self.buffer.len() {
+            let c = self.buffer.as_bytes()[self.position];
+            if self.in_string {
+                match c {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => self.in_string = false,
+                    _ => (),
+                }
+            } else {
+                match c {
+                    b'"' => self.in_string = true,
+                    b'{' | b'[' => {
+                        if self.depth == 0 {
+                            self.entry_start = self.position;
+                        }
+                        self.depth += 1;
+                    }
+                    b'}' | b']' if self.depth > 0 => {
+                        self.depth -= 1;
+                        if self.depth == 0 {
+                            let entry = &self.buffer[self.entry_start..=self.position];
+                            if let Ok(entry) = serde_json::from_str::<Value>(entry) {
+                                commands.extend(self.repairer.command(&entry));
+                            }
+                        }
+                    }
+                    b']' => self.state = ParserState::Done,
+                    _ => (),
+                }
+            }
+            self.position += 1;
+        }
+
+        commands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+    use crate::models::Direction;
+
+    fn commands() -> Vec<Commands> {
+        vec![
+            Commands {
+                command: Direction::Right,
+                repeat: 58,
+            },
+            Commands {
+                command: Direction::Up,
+                repeat: 9,
+            },
+        ]
+    }
+
+    #[rstest]
+    #[case(1)]
+    #[case(3)]
+    #[case(7)]
+    #[case(1000)]
+    fn test_parser_releases_every_entry(#[case] chunk_size: usize) {
+        let text = r#"Sure! {"commands": [{"command": "right", "repeat": 58}, {"command": "up", "repeat": 9}]} done"#;
+        let mut parser = CommandStreamParser::new(&BoardView::default());
+        let mut parsed = Vec::new();
+
+        for chunk in text.as_bytes().chunks(chunk_size) {
+            parsed.extend(parser.push(std::str::from_utf8(chunk).unwrap()));
+        }
+
+        assert_eq!(parsed, commands());
+    }
+
+    #[test]
+    fn test_parser_releases_entry_before_end() {
+        let mut parser = CommandStreamParser::new(&BoardView::default());
+
+        let first = parser.push(r#"{"commands": [{"command": "right", "repeat": 58}, {"com"#);
+
+        assert_eq!(first, commands()[..1]);
+    }
+
+    #[test]
+    fn test_parser_skips_the_reasoning() {
+        let mut parser = CommandStreamParser::new(&BoardView::default());
+
+        let thinking =
+            parser.push(r#"<think>maybe {"commands": [{"command": "left", "repeat": 1}]}"#);
+        let answer = parser.push(
+            r#"</think>{"commands": [{"command": "right", "repeat": 58}, {"command": "up", "repeat": 9}]}"#,
+        );
+
+        assert!(thinking.is_empty());
+        assert_eq!(answer, commands());
+    }
+
+    #[test]
+    fn test_read_sse() {
+        let stream = concat!(
+            "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"{\\\"commands\\\": [\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"]}\"}}]}\n\n",
+            "data: {\"choices\":[],\"x_groq\":{\"usage\":{\"prompt_tokens\":50,\"completion_tokens\":7}}}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let mut pieces = Vec::new();
+
+        let streamed = read_sse(stream.as_bytes(), |piece| pieces.push(piece.to_string())).unwrap();
+
+        assert_eq!(streamed.content, r#"{"commands": []}"#);
+        assert_eq!(pieces.len(), 2);
+        assert_eq!(streamed.tokens.map(|t| t.completion_tokens), Some(7));
+    }
+
+    #[test]
+    fn test_read_ndjson() {
+        let stream = concat!(
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"{\\\"commands\\\": [\"},\"done\":false}\n",
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"]}\"},\"done\":false}\n",
+            "{\"done\":true,\"prompt_eval_count\":40,\"eval_count\":20}\n",
+        );
+
+        let streamed = read_ndjson(stream.as_bytes(), |_| {}).unwrap();
+
+        assert_eq!(streamed.content, r#"{"commands": []}"#);
+        assert_eq!(streamed.tokens.map(|t| t.prompt_tokens), Some(40));
+    }
+
+    #[test]
+    fn test_read_completion_sse() {
+        let stream = concat!(
+            "data: {\"content\":\"{\\\"commands\\\": [\",\"stop\":false}\n\n",
+            "data: {\"content\":\"]}\",\"stop\":false}\n\n",
+            "data: {\"content\":\"\",\"stop\":true,\"tokens_evaluated\":80,\"tokens_predicted\":9}\n\n",
+        );
+
+        let streamed = read_completion_sse(stream.as_bytes(), |_| {}).unwrap();
+
+        assert_eq!(streamed.content, r#"{"commands": []}"#);
+        assert_eq!(streamed.tokens.map(|t| t.prompt_tokens), Some(80));
+    }
+
+    #[test]
+    fn test_read_messages_sse() {
+        let stream = include_str!("fixtures/anthropic/stream.txt");
+        let mut pieces = Vec::new();
+
+        let streamed =
+            read_messages_sse(stream.as_bytes(), |piece| pieces.push(piece.to_string())).unwrap();
+
+        assert_eq!(
+            streamed.content,
+            r#""commands": [{"command": "up", "repeat": 3}]}"#
+        );
+        assert_eq!(pieces.len(), 3);
+        assert_eq!(
+            streamed.tokens,
+            Some(Tokens {
+                prompt_tokens: 25,
+                completion_tokens: 17
+            })
+        );
+    }
+}
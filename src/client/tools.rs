@@ -0,0 +1,527 @@
+use std::time::{Duration, Instant};
+
+use log::info;
+use reqwest::{Client, StatusCode};
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    error::ClientError,
+    http,
+    models::{BoardView, Commands, InputContent, OutputContent, Role, TOOLS_SYSTEM_PROMPT},
+    repair::{self, Repairer},
+    transcript::Transcript,
+    usage::{Tokens, Usage},
+    ApiClient,
+};
+use crate::models::{Direction, Point};
+
+/// model turns in one request before giving up
+const MAX_TURNS: usize = 5;
+
+/// flavor of the tool calling API
+#[derive(Clone, Debug, PartialEq)]
+pub enum ToolApi {
+    /// OpenAI compatible chat completions, used by groq
+    OpenAi,
+    Ollama,
+}
+
+#[derive(Deserialize, Debug)]
+struct ToolCall {
+    #[serde(default)]
+    id: String,
+    function: FunctionCall,
+}
+
+#[derive(Deserialize, Debug)]
+struct FunctionCall {
+    name: String,
+    /// JSON string for OpenAI, object for Ollama
+    #[serde(default)]
+    arguments: Value,
+}
+
+/// Client that controls the snake with move/look/get_state tool calls,
+/// the model can call the tools for several turns until it plans the moves
+pub struct ToolClient {
+    client: Client,
+    api: ToolApi,
+    url: String,
+    token: String,
+    model: String,
+    temperature: Option<f32>,
+    timeout: Option<Duration>,
+    cancel: CancellationToken,
+    system_prompt: String,
+    board: BoardView,
+    usage: Usage,
+    /// tool calls of the last request
+    transcript: Transcript,
+}
+
+impl ApiClient for ToolClient {
+    fn snake_commands(&mut self, input: InputContent) -> Result<OutputContent, ClientError> {
+        self.snake_commands(input)
+    }
+
+    fn observe_board(&mut self, board: &BoardView) {
+        self.board = board.clone();
+    }
+
+    fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancel = token;
+    }
+
+    fn usage(&self) -> Usage {
+        self.usage.clone()
+    }
+
+    fn take_transcript(&mut self) -> Option<Transcript> {
+        Some(std::mem::take(&mut self.transcript))
+    }
+}
+
+impl ToolClient {
+    pub fn new(api: ToolApi, url: String, token: String, model: String) -> Self {
+        Self {
+            client: Client::new(),
+            api,
+            url,
+            token,
+            model,
+            temperature: None,
+            timeout: None,
+            cancel: CancellationToken::new(),
+            system_prompt: TOOLS_SYSTEM_PROMPT.to_string(),
+            board: BoardView::default(),
+            usage: Usage::default(),
+            transcript: Transcript::default(),
+        }
+    }
+
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn snake_commands(&mut self, input: InputContent) -> Result<OutputContent, ClientError> {
+        let board = self.board.clone();
+        let mut session = Session::new(&input, &board);
+        let mut messages = vec![
+            json!({ "role": Role::System.as_string(), "content": self.system_prompt }),
+            json!({
+                "role": Role::User.as_string(),
+                "content": serde_json::to_string(&input).unwrap(),
+            }),
+        ];
+        self.transcript = Transcript {
+            prompt: messages
+                .iter()
+                .map(|m| {
+                    let text = |key: &str| m[key].as_str().unwrap_or_default().to_string();
+                    format!("{}: {}", text("role"), text("content"))
+                })
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            ..Default::default()
+        };
+
+        for _ in 0..MAX_TURNS {
+            let message = self.chat(&messages)?;
+            let calls: Vec<ToolCall> =
+                serde_json::from_value(message["tool_calls"].clone()).unwrap_or_default();
+            messages.push(message.clone());
+            if let Some(content) = message["content"].as_str() {
+                self.transcript.response.push_str(content);
+            }
+
+            if calls.is_empty() {
+                return Err(ClientError::Other(format!(
+                    "Model answered without calling move: {}",
+                    message["content"]
+                )));
+            }
+            for call in calls {
+                let arguments = parse_arguments(&call.function.arguments);
+                let result = session.call(&call.function.name, &arguments);
+                info!("{}({}) -> {}", call.function.name, arguments, result);
+                self.transcript.tool_calls.push(format!(
+                    "{}({}) -> {}",
+                    call.function.name, arguments, result
+                ));
+                messages.push(self.tool_message(&call, result));
+            }
+            self.transcript
+                .repairs
+                .append(&mut session.repairer.repairs);
+            if !session.commands.is_empty() {
+                return Ok(OutputContent {
+                    commands: session.commands,
+                });
+            }
+        }
+
+        Err(ClientError::Other(format!(
+            "No move after {} turns",
+            MAX_TURNS
+        )))
+    }
+
+    /// sends the conversation and returns the assistant message
+    fn chat(&mut self, messages: &[Value]) -> Result<Value, ClientError> {
+        let sent_at = Instant::now();
+        let mut body = json!({
+            "model": self.model,
+            "messages": messages,
+            "tools": tool_definitions(),
+            "stream": false,
+        });
+        if let Some(temperature) = self.temperature {
+            match self.api {
+                ToolApi::OpenAi => body["temperature"] = json!(temperature),
+                ToolApi::Ollama => body["options"] = json!({ "temperature": temperature }),
+            }
+        }
+
+        let mut request = self.client.post(&self.url).body(body.to_string());
+        if !self.token.is_empty() {
+            request = request.bearer_auth(&self.token);
+        }
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+        let resp = http::send(request, &self.cancel)?;
+
+        let status = resp.status();
+        if status != StatusCode::OK {
+            return Err(ClientError::Status(status));
+        }
+
+        let resp_body = http::text(resp, &self.cancel)?;
+        let resp: Value = serde_json::from_str(&resp_body)
+            .map_err(|e| format!("Failed to parse response body {}", e))?;
+        let message = self.assistant_message(&resp)?;
+        self.usage.record(tokens(&resp), sent_at.elapsed());
+        Ok(message)
+    }
+
+    fn assistant_message(&self, resp: &Value) -> Result<Value, String> {
+        let message = match self.api {
+            ToolApi::OpenAi => &resp["choices"][0]["message"],
+            ToolApi::Ollama => &resp["message"],
+        };
+        if message.is_object() {
+            Ok(message.clone())
+        } else {
+            Err(format!("No message in response: {}", resp))
+        }
+    }
+
+    fn tool_message(&self, call: &ToolCall, result: String) -> Value {
+        match self.api {
+            ToolApi::OpenAi => json!({
+                "role": "tool",
+                "tool_call_id": call.id,
+                "content": result,
+            }),
+            ToolApi::Ollama => json!({
+                "role": "tool",
+                "tool_name": call.function.name,
+                "content": result,
+            }),
+        }
+    }
+}
+
+/// usage reported in the OpenAI `usage` block or the Ollama counters
+fn tokens(resp: &Value) -> Tokens {
+    let count = |value: &Value| value.as_u64().unwrap_or_default();
+    match resp.get("usage") {
+        Some(usage) => Tokens {
+            prompt_tokens: count(&usage["prompt_tokens"]),
+            completion_tokens: count(&usage["completion_tokens"]),
+        },
+        None => Tokens {
+            prompt_tokens: count(&resp["prompt_eval_count"]),
+            completion_tokens: count(&resp["eval_count"]),
+        },
+    }
+}
+
+/// OpenAI arguments are a JSON string, Ollama ones are already an object
+fn parse_arguments(arguments: &Value) -> Value {
+    match arguments {
+        Value::String(s) => serde_json::from_str(s).unwrap_or(Value::Null),
+        Value::Null => json!({}),
+        other => other.clone(),
+    }
+}
+
+/// the tools in the format shared by OpenAI and Ollama
+fn tool_definitions() -> Value {
+    json!([
+        {
+            "type": "function",
+            "function": {
+                "name": "move",
+                "description": "Plan moving the snake head the given number of cells in the direction",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "direction": { "type": "string", "enum": ["up", "down", "left", "right"] },
+                        "steps": { "type": "integer", "minimum": 1 }
+                    },
+                    "required": ["direction", "steps"]
+                }
+            }
+        },
+        {
+            "type": "function",
+            "function": {
+                "name": "look",
+                "description": "What is in the cells next to the snake head: empty, food, wall or body",
+                "parameters": { "type": "object", "properties": {} }
+            }
+        },
+        {
+            "type": "function",
+            "function": {
+                "name": "get_state",
+                "description": "Board size, snake head, snake length and food coordinates",
+                "parameters": { "type": "object", "properties": {} }
+            }
+        }
+    ])
+}
+
+/// Tools of one request, moves are planned from the head
+/// so look() after a move describes the cells around the planned position
+struct Session<'a> {
+    input: &'a InputContent,
+    board: &'a BoardView,
+    head: Point,
+    commands: Vec<Commands>,
+    /// clamps the moves to the board and rejects the reversals
+    repairer: Repairer,
+}
+
+impl<'a> Session<'a> {
+    fn new(input: &'a InputContent, board: &'a BoardView) -> Self {
+        Self {
+            input,
+            board,
+            head: Point::new(input.snake_head_x, input.snake_head_y),
+            commands: Vec::new(),
+            repairer: Repairer::new(board),
+        }
+    }
+
+    fn call(&mut self, name: &str, arguments: &Value) -> String {
+        match name {
+            "move" => self.move_head(arguments),
+            "look" => self.look(),
+            "get_state" => self.get_state(),
+            _ => format!("error: unknown tool {}", name),
+        }
+    }
+
+    fn move_head(&mut self, arguments: &Value) -> String {
+        let direction: Direction = match serde_json::from_value(arguments["direction"].clone()) {
+            Ok(direction) => direction,
+            Err(_) => return "error: direction must be up, down, left or right".to_string(),
+        };
+        if !matches!(arguments["steps"].as_i64(), Some(steps) if steps > 0) {
+            return "error: steps must be a positive integer".to_string();
+        }
+
+        let repairs = self.repairer.repairs.len();
+        let entry = json!({
+            "command": repair::direction_name(&direction),
+            "repeat": arguments["steps"],
+        });
+        let Some(command) = self.repairer.command(&entry) else {
+            let problem = self.repairer.repairs.last().cloned().unwrap_or_default();
+            return format!("error: {}", problem);
+        };
+        let (dx, dy) = command.command.delta();
+        self.head = Point::new(
+            self.head.x.saturating_add(dx * command.repeat),
+            self.head.y.saturating_add(dy * command.repeat),
+        );
+        let notes: String = self.repairer.repairs[repairs..]
+            .iter()
+            .map(|r| format!("{}, ", r))
+            .collect();
+        self.commands.push(command);
+        format!(
+            "ok, {}the head will be at x={}, y={}",
+            notes, self.head.x, self.head.y
+        )
+    }
+
+    fn look(&self) -> String {
+        let cells: serde_json::Map<String, Value> = [
+            Direction::Up,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+        ]
+        .iter()
+        .map(|direction| {
+            let (dx, dy) = direction.delta();
+            let cell = self.cell(self.head.x + dx, self.head.y + dy);
+            (
+                serde_json::to_value(direction)
+                    .unwrap()
+                    .as_str()
+                    .unwrap()
+                    .to_string(),
+                json!(cell),
+            )
+        })
+        .collect();
+        Value::Object(cells).to_string()
+    }
+
+    fn get_state(&self) -> String {
+        json!({
+            "width": self.board.width,
+            "height": self.board.height,
+            "snake_head_x": self.head.x,
+            "snake_head_y": self.head.y,
+            "food_x": self.input.food_x,
+            "food_y": self.input.food_y,
+        })
+        .to_string()
+    }
+
+    fn cell(&self, x: i32, y: i32) -> &'static str {
+        if x < 0 || y < 0 || x >= self.board.width || y >= self.board.height {
+            "wall"
+        } else if (x, y) == (self.input.food_x, self.input.food_y) {
+            "food"
+        } else if self.board.occupied.iter().any(|p| (p.x, p.y) == (x, y)) {
+            "body"
+        } else {
+            "empty"
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    fn input() -> InputContent {
+        InputContent {
+            snake_head_x: 0,
+            snake_head_y: 5,
+            food_x: 1,
+            food_y: 5,
+        }
+    }
+
+    fn board() -> BoardView {
+        BoardView {
+            width: 10,
+            height: 10,
+            occupied: vec![Point::new(0, 5), Point::new(0, 4)],
+            snake: Vec::new(),
+            heading: Some(Direction::Up),
+        }
+    }
+
+    #[test]
+    fn test_look() {
+        let (input, board) = (input(), board());
+        let session = Session::new(&input, &board);
+
+        let cells: Value = serde_json::from_str(&session.look()).unwrap();
+
+        assert_eq!(
+            cells,
+            json!({ "up": "empty", "down": "body", "left": "wall", "right": "food" })
+        );
+    }
+
+    #[rstest]
+    #[case(json!({ "direction": "right", "steps": 3 }), 1)]
+    #[case(json!({ "direction": "north", "steps": 3 }), 0)]
+    #[case(json!({ "direction": "up", "steps": 0 }), 0)]
+    #[case(json!({ "direction": "down", "steps": 2 }), 0)]
+    #[case(json!({}), 0)]
+    fn test_move(#[case] arguments: Value, #[case] commands: usize) {
+        let (input, board) = (input(), board());
+        let mut session = Session::new(&input, &board);
+
+        let result = session.call("move", &arguments);
+
+        assert_eq!(session.commands.len(), commands);
+        assert_eq!(result.starts_with("error"), commands == 0);
+    }
+
+    #[test]
+    fn test_look_after_move() {
+        let (input, board) = (input(), board());
+        let mut session = Session::new(&input, &board);
+
+        session.call("move", &json!({ "direction": "up", "steps": 4 }));
+        let state: Value = serde_json::from_str(&session.get_state()).unwrap();
+
+        assert_eq!(state["snake_head_y"], 9);
+        assert!(session.look().contains(r#""up":"wall""#));
+    }
+
+    #[rstest]
+    #[case(
+        board(),
+        10000,
+        9,
+        "ok, repeat 10000 of up clamped to 9, the head will be at x=0, y=14"
+    )]
+    #[case(BoardView::default(), i64::MAX, i32::MAX, "ok, repeat 9223372036854775807 of up clamped to 2147483647, the head will be at x=0, y=2147483647")]
+    fn test_move_clamped(
+        #[case] board: BoardView,
+        #[case] steps: i64,
+        #[case] repeat: i32,
+        #[case] result: &str,
+    ) {
+        let input = input();
+        let mut session = Session::new(&input, &board);
+
+        let answer = session.call("move", &json!({ "direction": "up", "steps": steps }));
+
+        assert_eq!(session.commands[0].repeat, repeat);
+        assert_eq!(answer, result);
+    }
+
+    #[rstest]
+    #[case(ToolApi::OpenAi, json!({ "choices": [{ "message": { "role": "assistant", "tool_calls": [
+        { "id": "call_1", "type": "function", "function": { "name": "move", "arguments": "{\"direction\": \"up\", \"steps\": 2}" } }
+    ] } }] }))]
+    #[case(ToolApi::Ollama, json!({ "message": { "role": "assistant", "content": "", "tool_calls": [
+        { "function": { "name": "move", "arguments": { "direction": "up", "steps": 2 } } }
+    ] }, "done": true }))]
+    fn test_parse_tool_calls(#[case] api: ToolApi, #[case] resp: Value) {
+        let client = ToolClient::new(api, String::new(), String::new(), String::new());
+
+        let message = client.assistant_message(&resp).unwrap();
+        let calls: Vec<ToolCall> = serde_json::from_value(message["tool_calls"].clone()).unwrap();
+
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].function.name, "move");
+        assert_eq!(
+            parse_arguments(&calls[0].function.arguments),
+            json!({ "direction": "up", "steps": 2 })
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use super::{
+    models::{BoardView, Message},
+    repair,
+};
+
+/// What was sent to the model and what it answered, for the inspector
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Transcript {
+    /// messages of the last call, one `role: content` block each
+    pub prompt: String,
+    /// answer of the last call as the model wrote it, reasoning included
+    pub response: String,
+    /// fixes of the parser and the corrections asked from the model
+    pub repairs: Vec<String>,
+    /// tools the model called and what they returned, e.g. `look({}) -> {"up":"empty",...}`
+    pub tool_calls: Vec<String>,
+}
+
+impl Transcript {
+    /// Keeps the last call, the repairs of its answer are added to the corrections asked before
+    pub fn record(
+        &mut self,
+        messages: &[Message],
+        response: &str,
+        answer: &str,
+        board: &BoardView,
+    ) {
+        self.prompt = messages
+            .iter()
+            .map(|m| format!("{}: {}", m.role, m.content))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        self.response = response.to_string();
+        if let Ok(repaired) = repair::parse_output(answer, board) {
+            self.repairs.extend(repaired.repairs);
+        }
+    }
+
+    pub fn correction(&mut self, problem: &str) {
+        self.repairs
+            .push(format!("asked for a correction: {}", problem));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::models::Role;
+
+    #[test]
+    fn test_record_keeps_the_corrections() {
+        let messages = vec![
+            Message {
+                role: Role::System.as_string(),
+                content: "Play snake.".to_string(),
+            },
+            Message {
+                role: Role::User.as_string(),
+                content: "{}".to_string(),
+            },
+        ];
+        let answer = "```json\n{\"commands\": [{\"command\": \"up\", \"repeat\": 3}]}\n```";
+        let mut transcript = Transcript::default();
+
+        transcript.correction("moving up 12 hits the wall at y=40");
+        transcript.record(&messages, answer, answer, &BoardView::default());
+
+        assert_eq!(transcript.prompt, "system: Play snake.\n\nuser: {}");
+        assert_eq!(transcript.response, answer);
+        assert_eq!(
+            transcript.repairs,
+            vec![
+                "asked for a correction: moving up 12 hits the wall at y=40",
+                "removed the code fence"
+            ]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use std::time::Duration;
+
+use serde_derive::{Deserialize, Serialize};
+
+use super::{memory, models::Message};
+
+/// tokens of one call as reported by the server
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Tokens {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl Tokens {
+    /// estimate for the servers that don't report the usage
+    pub fn estimate(messages: &[Message], answer: &str) -> Self {
+        Self {
+            prompt_tokens: messages
+                .iter()
+                .map(|m| memory::estimate_tokens(&m.content) as u64)
+                .sum(),
+            completion_tokens: memory::estimate_tokens(answer) as u64,
+        }
+    }
+}
+
+/// Price of a model in dollars per million tokens
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Pricing {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// Tokens and time spent on the calls of a client
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Usage {
+    pub calls: u32,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub latency: Duration,
+}
+
+impl Usage {
+    pub fn record(&mut self, tokens: Tokens, latency: Duration) {
+        self.calls += 1;
+        self.prompt_tokens += tokens.prompt_tokens;
+        self.completion_tokens += tokens.completion_tokens;
+        self.latency += latency;
+    }
+
+    pub fn add(&mut self, other: &Usage) {
+        self.calls += other.calls;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.latency += other.latency;
+    }
+
+    /// calls made since the `earlier` snapshot of the same client
+    pub fn since(&self, earlier: &Usage) -> Usage {
+        Usage {
+            calls: self.calls.saturating_sub(earlier.calls),
+            prompt_tokens: self.prompt_tokens.saturating_sub(earlier.prompt_tokens),
+            completion_tokens: self
+                .completion_tokens
+                .saturating_sub(earlier.completion_tokens),
+            latency: self.latency.saturating_sub(earlier.latency),
+        }
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    pub fn avg_latency(&self) -> Duration {
+        if self.calls == 0 {
+            return Duration::ZERO;
+        }
+        self.latency / self.calls
+    }
+
+    /// estimated cost in dollars
+    pub fn cost(&self, pricing: &Pricing) -> f64 {
+        (self.prompt_tokens as f64 * pricing.input + self.completion_tokens as f64 * pricing.output)
+            / 1_000_000.0
+    }
+
+    /// e.g. "1520 tokens / 4 calls / 830 ms avg / 2.0 pts per 1k tokens / $0.0012"
+    pub fn summary(&self, score: u32, pricing: Option<&Pricing>) -> String {
+        let per_1k = if self.total_tokens() == 0 {
+            0.0
+        } else {
+            score as f64 * 1000.0 / self.total_tokens() as f64
+        };
+        let mut summary = format!(
+            "{} tokens / {} calls / {} ms avg / {:.1} pts per 1k tokens",
+            self.total_tokens(),
+            self.calls,
+            self.avg_latency().as_millis(),
+            per_1k
+        );
+        if let Some(pricing) = pricing {
+            summary.push_str(&format!(" / ${:.4}", self.cost(pricing)));
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    fn usage() -> Usage {
+        let mut usage = Usage::default();
+        usage.record(
+            Tokens {
+                prompt_tokens: 900,
+                completion_tokens: 100,
+            },
+            Duration::from_millis(600),
+        );
+        usage.record(
+            Tokens {
+                prompt_tokens: 900,
+                completion_tokens: 100,
+            },
+            Duration::from_millis(1000),
+        );
+        usage
+    }
+
+    #[rstest]
+    #[case(None, "2000 tokens / 2 calls / 800 ms avg / 2.0 pts per 1k tokens")]
+    #[case(
+        Some(Pricing { input: 0.5, output: 1.5 }),
+        "2000 tokens / 2 calls / 800 ms avg / 2.0 pts per 1k tokens / $0.0012"
+    )]
+    fn test_summary(#[case] pricing: Option<Pricing>, #[case] expected: &str) {
+        assert_eq!(usage().summary(4, pricing.as_ref()), expected);
+    }
+
+    #[test]
+    fn test_since() {
+        let earlier = usage();
+        let mut later = usage();
+        later.record(Tokens::default(), Duration::from_millis(200));
+
+        let delta = later.since(&earlier);
+
+        assert_eq!(delta.calls, 1);
+        assert_eq!(delta.total_tokens(), 0);
+        assert_eq!(delta.latency, Duration::from_millis(200));
+    }
+}
@@ -1,19 +1,58 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
 use figment::{
-    providers::{Format, Yaml},
+    providers::{Env, Format, Yaml},
     Figment,
 };
+use reqwest::Url;
 use serde_derive::Deserialize;
 
-#[derive(Deserialize)]
+use crate::client::{
+    ensemble::Strategy,
+    fallback::{self, ErrorKind},
+    few_shot::FewShot,
+    groq::GroqModels,
+    memory::HistoryPolicy,
+    models::{PromptVariant, Protocol},
+    prompt::PromptTemplate,
+    usage::Pricing,
+};
+use crate::models::Provider;
+
+const GROQ_URL: &str = "https://api.groq.com/openai/v1/chat/completions";
+const OLLAMA_URL: &str = "http://localhost:11434/api/chat";
+const LLAMA_CPP_URL: &str = "http://localhost:8080/completion";
+const ANTHROPIC_URL: &str = "https://api.anthropic.com/v1/messages";
+const GEMINI_URL: &str = "https://generativelanguage.googleapis.com/v1beta/models";
+const GROQ_TOKEN_ENV: &str = "GROQ_API_KEY";
+const ANTHROPIC_TOKEN_ENV: &str = "ANTHROPIC_API_KEY";
+const GEMINI_TOKEN_ENV: &str = "GEMINI_API_KEY";
+const ENV_PREFIX: &str = "SNAKE_LLM_";
+
+#[derive(Deserialize, Default)]
 pub struct Config {
     pub groq_client: Option<TokenClient>,
     pub ollama_client: Option<Client>,
+    pub llama_cpp_client: Option<Client>,
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    #[serde(default)]
+    pub ensembles: Vec<EnsembleProfile>,
+    #[serde(default)]
+    pub fallbacks: Vec<FallbackProfile>,
+    #[serde(default)]
+    pub arena: ArenaSettings,
 }
 
 #[derive(Deserialize)]
 pub struct TokenClient {
+    #[serde(default = "default_groq_url")]
     pub url: String,
     pub token: String,
+    pub model: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -22,11 +61,548 @@ pub struct Client {
     pub model: String,
 }
 
-pub fn parse() -> Config {
-    let config: Config = Figment::new()
-        .join(Yaml::file("config.yaml"))
+/// Named model config, several profiles can use the same provider
+#[derive(Deserialize, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub provider: Provider,
+    /// provider's default url when empty
+    #[serde(default)]
+    pub url: String,
+    /// taken from GROQ_API_KEY, ANTHROPIC_API_KEY or GEMINI_API_KEY for the hosted providers when empty
+    #[serde(default)]
+    pub token: String,
+    pub model: String,
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub prompt: PromptVariant,
+    /// YAML prompt template, used instead of `prompt` when set, relative to the config file
+    pub prompt_file: Option<PathBuf>,
+    /// loaded from prompt_file by validate
+    #[serde(skip)]
+    pub template: Option<PromptTemplate>,
+    pub timeout_secs: Option<u64>,
+    /// apply the commands while the response is still being generated
+    #[serde(default)]
+    pub stream: bool,
+    /// ask the provider for JSON matching the answer schema instead of relying on the prompt
+    #[serde(default = "default_true")]
+    pub structured_output: bool,
+    #[serde(default)]
+    pub protocol: Protocol,
+    /// times the model is asked to fix a wrong answer
+    #[serde(default)]
+    pub max_corrections: u32,
+    #[serde(default)]
+    pub history: HistoryPolicy,
+    /// context size of the model, guessed from the model name when missing
+    pub context_tokens: Option<usize>,
+    /// examples of solved states sent with every request
+    pub few_shot: Option<FewShot>,
+    /// dollars per million tokens, used to estimate the cost of the games
+    pub pricing: Option<Pricing>,
+    /// answer the states asked before from the response cache
+    #[serde(default)]
+    pub cache: bool,
+}
+
+impl Profile {
+    fn new(name: &str, provider: Provider, url: String, token: String, model: String) -> Self {
+        Self {
+            name: name.to_string(),
+            provider,
+            url,
+            token,
+            model,
+            temperature: None,
+            prompt: PromptVariant::default(),
+            prompt_file: None,
+            template: None,
+            timeout_secs: None,
+            stream: false,
+            structured_output: true,
+            protocol: Protocol::default(),
+            max_corrections: 0,
+            history: HistoryPolicy::default(),
+            context_tokens: None,
+            few_shot: None,
+            pricing: None,
+            cache: false,
+        }
+    }
+
+    fn validate(&mut self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.url.is_empty() {
+            self.url = match self.provider {
+                Provider::Groq => GROQ_URL.to_string(),
+                Provider::Ollama => OLLAMA_URL.to_string(),
+                Provider::LlamaCpp => LLAMA_CPP_URL.to_string(),
+                Provider::Anthropic => ANTHROPIC_URL.to_string(),
+                Provider::Gemini => GEMINI_URL.to_string(),
+            };
+        }
+        problems.extend(validate_url(&self.url));
+
+        let token_env = match self.provider {
+            Provider::Groq => Some(GROQ_TOKEN_ENV),
+            Provider::Anthropic => Some(ANTHROPIC_TOKEN_ENV),
+            Provider::Gemini => Some(GEMINI_TOKEN_ENV),
+            Provider::Ollama | Provider::LlamaCpp => None,
+        };
+        if let Some(token_env) = token_env {
+            if self.token.trim().is_empty() {
+                self.token = env::var(token_env).unwrap_or_default();
+            }
+            if self.token.trim().is_empty() {
+                problems.push(format!(
+                    "token is empty, set it in config.yaml or in {}",
+                    token_env
+                ));
+            }
+        }
+        if self.model.trim().is_empty() {
+            problems.push("model is empty".to_string());
+        }
+        if matches!(
+            self.provider,
+            Provider::LlamaCpp | Provider::Anthropic | Provider::Gemini
+        ) && self.protocol == Protocol::Tools
+        {
+            problems.push(format!(
+                "{} doesn't support protocol: tools",
+                self.provider.id()
+            ));
+        }
+        // the Messages API of anthropic takes at most 1
+        let max_temperature = match self.provider {
+            Provider::Anthropic => 1.0,
+            _ => 2.0,
+        };
+        if matches!(self.temperature, Some(t) if !(0.0..=max_temperature).contains(&t)) {
+            problems.push(format!(
+                "temperature must be between 0 and {}",
+                max_temperature
+            ));
+        }
+        if matches!(
+            self.history,
+            HistoryPolicy::Window { turns: 0 } | HistoryPolicy::Summary { turns: 0 }
+        ) {
+            problems.push("history turns must be greater than 0".to_string());
+        }
+        if matches!(&self.few_shot, Some(f) if f.examples == 0) {
+            problems.push("few_shot examples must be greater than 0".to_string());
+        }
+        if matches!(&self.pricing, Some(p) if p.input < 0.0 || p.output < 0.0) {
+            problems.push("pricing can't be negative".to_string());
+        }
+        if let Some(path) = &self.prompt_file {
+            match PromptTemplate::load(path) {
+                Ok(template) => self.template = Some(template),
+                Err(e) => problems.push(e),
+            }
+        }
+        if self.timeout_secs == Some(0) {
+            problems.push("timeout_secs must be greater than 0".to_string());
+        }
+
+        problems
+    }
+}
+
+/// Profiles asked about the same state at the same time, their plans are combined by the strategy
+#[derive(Deserialize, Clone)]
+pub struct EnsembleProfile {
+    pub name: String,
+    /// names of the profiles
+    pub members: Vec<String>,
+    #[serde(default)]
+    pub strategy: Strategy,
+}
+
+/// Profiles tried in order, the next one plays when the active one fails
+#[derive(Deserialize, Clone)]
+pub struct FallbackProfile {
+    pub name: String,
+    /// names of the profiles, `bot` is the BFS bot
+    pub chain: Vec<String>,
+    #[serde(default = "fallback::default_switch_on")]
+    pub switch_on: Vec<ErrorKind>,
+    /// a provider that doesn't answer in time fails with a timeout
+    pub timeout_secs: Option<u64>,
+    /// the first provider is tried again after this time
+    #[serde(default = "default_retry_secs")]
+    pub retry_secs: u64,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ArenaSettings {
+    pub width: u16,
+    pub height: u16,
+    pub max_ticks: u32,
+    pub seed: u64,
+    pub rounds: u32,
+}
+
+impl Default for ArenaSettings {
+    fn default() -> Self {
+        Self {
+            width: 60,
+            height: 40,
+            max_ticks: 1000,
+            seed: 42,
+            rounds: 3,
+        }
+    }
+}
+
+impl Config {
+    /// Turns groq_client and ollama_client into the "groq" and "ollama" profiles,
+    /// drops the profiles that can't be used and returns what was wrong with them
+    pub fn validate(&mut self) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        let mut legacy = Vec::new();
+        if let Some(cfg) = self.groq_client.take() {
+            let model = cfg
+                .model
+                .unwrap_or_else(|| GroqModels::Llama3b70.as_string());
+            legacy.push(Profile::new(
+                "groq",
+                Provider::Groq,
+                cfg.url,
+                cfg.token,
+                model,
+            ));
+        }
+        if let Some(cfg) = self.ollama_client.take() {
+            legacy.push(Profile::new(
+                "ollama",
+                Provider::Ollama,
+                cfg.url,
+                String::new(),
+                cfg.model,
+            ));
+        }
+        if let Some(cfg) = self.llama_cpp_client.take() {
+            legacy.push(Profile::new(
+                &Provider::LlamaCpp.id(),
+                Provider::LlamaCpp,
+                cfg.url,
+                String::new(),
+                cfg.model,
+            ));
+        }
+        legacy.append(&mut self.profiles);
+
+        for mut profile in legacy {
+            let mut problems = profile.validate();
+            if profile.name.trim().is_empty() {
+                problems.push("name is empty".to_string());
+            }
+            if self.profiles.iter().any(|p| p.name == profile.name) {
+                problems.push("name is already used by another profile".to_string());
+            }
+
+            if problems.is_empty() {
+                self.profiles.push(profile);
+            } else {
+                errors.extend(
+                    problems
+                        .iter()
+                        .map(|p| format!("profile {}: {}", profile.name, p)),
+                );
+            }
+        }
+
+        for ensemble in std::mem::take(&mut self.ensembles) {
+            let mut problems = Vec::new();
+            if ensemble.name.trim().is_empty() {
+                problems.push("name is empty".to_string());
+            }
+            if self.profiles.iter().any(|p| p.name == ensemble.name)
+                || self.ensembles.iter().any(|e| e.name == ensemble.name)
+            {
+                problems.push("name is already used by another profile".to_string());
+            }
+            if ensemble.members.len() < 2 {
+                problems.push("an ensemble needs at least 2 members".to_string());
+            }
+            for member in &ensemble.members {
+                if !self.profiles.iter().any(|p| p.name == *member) {
+                    problems.push(format!("member {} is not a valid profile", member));
+                }
+            }
+
+            if problems.is_empty() {
+                self.ensembles.push(ensemble);
+            } else {
+                errors.extend(
+                    problems
+                        .iter()
+                        .map(|p| format!("ensemble {}: {}", ensemble.name, p)),
+                );
+            }
+        }
+
+        for chain in std::mem::take(&mut self.fallbacks) {
+            let mut problems = Vec::new();
+            if chain.name.trim().is_empty() {
+                problems.push("name is empty".to_string());
+            }
+            if self.profiles.iter().any(|p| p.name == chain.name)
+                || self.ensembles.iter().any(|e| e.name == chain.name)
+                || self.fallbacks.iter().any(|f| f.name == chain.name)
+            {
+                problems.push("name is already used by another profile".to_string());
+            }
+            if chain.chain.len() < 2 {
+                problems.push("a fallback chain needs at least 2 providers".to_string());
+            }
+            for member in &chain.chain {
+                if member != fallback::BOT && !self.profiles.iter().any(|p| p.name == *member) {
+                    problems.push(format!("{} is not a valid profile", member));
+                }
+            }
+            if chain.timeout_secs == Some(0) {
+                problems.push("timeout_secs must be greater than 0".to_string());
+            }
+
+            if problems.is_empty() {
+                self.fallbacks.push(chain);
+            } else {
+                errors.extend(
+                    problems
+                        .iter()
+                        .map(|p| format!("fallback {}: {}", chain.name, p)),
+                );
+            }
+        }
+
+        if self.arena.width == 0 || self.arena.height == 0 {
+            errors.push("arena: width and height must be greater than 0".to_string());
+            self.arena = ArenaSettings::default();
+        }
+
+        errors
+    }
+}
+
+/// Reads the config file, a missing file gives an empty config.
+/// A relative `prompt_file` is resolved against the directory of the config file.
+/// Values from the file are overridden by GROQ_API_KEY and SNAKE_LLM_* environment variables,
+/// nested keys are separated by `__`, e.g. SNAKE_LLM_OLLAMA_CLIENT__MODEL=phi3
+pub fn parse(path: &Path) -> Result<Config, String> {
+    let mut figment = Figment::new().join(Yaml::file(path));
+    if let Ok(token) = env::var(GROQ_TOKEN_ENV) {
+        figment = figment.merge(("groq_client.token", token));
+    }
+    figment = figment.merge(Env::prefixed(ENV_PREFIX).split("__"));
+
+    let mut config: Config = figment
         .extract()
+        .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for profile in &mut config.profiles {
+        profile.prompt_file = profile.prompt_file.take().map(|file| dir.join(file));
+    }
+    Ok(config)
+}
+
+fn validate_url(url: &str) -> Option<String> {
+    match Url::parse(url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => None,
+        Ok(url) => Some(format!("unsupported url scheme: {}", url.scheme())),
+        Err(e) => Some(format!("bad url {:?}: {}", url, e)),
+    }
+}
+
+fn default_groq_url() -> String {
+    GROQ_URL.to_string()
+}
+
+fn default_retry_secs() -> u64 {
+    60
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[case(
+        "https://api.groq.com/openai/v1/chat/completions",
+        Some("llama3-8b-8192"),
+        0
+    )]
+    #[case("", None, 0)]
+    #[case("api.groq.com/openai", None, 1)]
+    #[case("ftp://api.groq.com", None, 1)]
+    #[case("not a url", Some(" "), 2)]
+    fn test_validate_groq(#[case] url: &str, #[case] model: Option<&str>, #[case] errors: usize) {
+        let mut config = Config {
+            groq_client: Some(TokenClient {
+                url: url.to_string(),
+                token: "key".to_string(),
+                model: model.map(str::to_string),
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(config.validate().len(), errors);
+        assert_eq!(config.profiles.len(), if errors == 0 { 1 } else { 0 });
+    }
+
+    #[rstest]
+    #[case("phi3", Provider::Ollama, Some(0.5), None, 0)]
+    #[case("phi3", Provider::Ollama, Some(1.5), None, 0)]
+    #[case("phi3", Provider::Ollama, Some(3.0), None, 1)]
+    #[case("claude", Provider::Anthropic, Some(1.0), None, 0)]
+    #[case("claude", Provider::Anthropic, Some(1.5), None, 1)]
+    #[case("phi3", Provider::Ollama, None, Some(0), 1)]
+    #[case("ollama", Provider::Ollama, None, None, 1)]
+    #[case("", Provider::Ollama, None, None, 1)]
+    fn test_validate_profiles(
+        #[case] name: &str,
+        #[case] provider: Provider,
+        #[case] temperature: Option<f32>,
+        #[case] timeout_secs: Option<u64>,
+        #[case] errors: usize,
+    ) {
+        let mut profile = Profile::new(
+            name,
+            provider,
+            String::new(),
+            "key".to_string(),
+            "phi3".to_string(),
+        );
+        profile.temperature = temperature;
+        profile.timeout_secs = timeout_secs;
+
+        let mut config = Config {
+            ollama_client: Some(Client {
+                url: "http://localhost:11434/api/chat".to_string(),
+                model: "llama3".to_string(),
+            }),
+            profiles: vec![profile],
+            ..Default::default()
+        };
+
+        assert_eq!(config.validate().len(), errors);
+        assert_eq!(config.profiles[0].name, "ollama");
+        assert_eq!(config.profiles.len(), 2 - errors);
+    }
+
+    #[rstest]
+    #[case("vote", vec!["ollama", "phi3"], 0)]
+    #[case("vote", vec!["ollama"], 1)]
+    #[case("vote", vec!["ollama", "mixtral"], 1)]
+    #[case("phi3", vec!["ollama", "phi3"], 1)]
+    fn test_validate_ensembles(
+        #[case] name: &str,
+        #[case] members: Vec<&str>,
+        #[case] errors: usize,
+    ) {
+        let mut config = Config {
+            ollama_client: Some(Client {
+                url: "http://localhost:11434/api/chat".to_string(),
+                model: "llama3".to_string(),
+            }),
+            profiles: vec![Profile::new(
+                "phi3",
+                Provider::Ollama,
+                String::new(),
+                String::new(),
+                "phi3".to_string(),
+            )],
+            ensembles: vec![EnsembleProfile {
+                name: name.to_string(),
+                members: members.into_iter().map(str::to_string).collect(),
+                strategy: Strategy::Majority,
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(config.validate().len(), errors);
+        assert_eq!(config.ensembles.len(), 1 - errors);
+    }
+
+    #[rstest]
+    #[case(vec!["phi3", "bot"], None, 0)]
+    #[case(vec!["phi3"], None, 1)]
+    #[case(vec!["phi3", "mixtral"], None, 1)]
+    #[case(vec!["phi3", "bot"], Some(0), 1)]
+    fn test_validate_fallbacks(
+        #[case] chain: Vec<&str>,
+        #[case] timeout_secs: Option<u64>,
+        #[case] errors: usize,
+    ) {
+        let mut config = Config {
+            profiles: vec![Profile::new(
+                "phi3",
+                Provider::Ollama,
+                String::new(),
+                String::new(),
+                "phi3".to_string(),
+            )],
+            fallbacks: vec![FallbackProfile {
+                name: "chain".to_string(),
+                chain: chain.into_iter().map(str::to_string).collect(),
+                switch_on: fallback::default_switch_on(),
+                timeout_secs,
+                retry_secs: 60,
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(config.validate().len(), errors);
+        assert_eq!(config.fallbacks.len(), 1 - errors);
+    }
+
+    #[test]
+    fn test_prompt_file_relative_to_config() {
+        let dir = env::temp_dir().join(format!("snake-llm-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.yaml");
+        std::fs::write(
+            &path,
+            "profiles:
+  - name: short
+    provider: ollama
+    model: phi3
+    prompt_file: prompts/short.yaml
+  - name: absolute
+    provider: ollama
+    model: phi3
+    prompt_file: /etc/short.yaml
+",
+        )
         .unwrap();
 
-    config
+        let config = parse(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let files: Vec<_> = config
+            .unwrap()
+            .profiles
+            .into_iter()
+            .map(|p| p.prompt_file.unwrap())
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                dir.join("prompts/short.yaml"),
+                PathBuf::from("/etc/short.yaml")
+            ]
+        );
+    }
 }
@@ -2,18 +2,26 @@ use std::time::{self};
 
 use crossterm::event;
 
-use crate::models::{Direction, GameMod, Provider};
+use crate::models::Direction;
+
+/// lines scrolled by page up and page down
+const SCROLL_LINES: i32 = 5;
 
 pub enum Command {
     Quit,
     Turn(Direction),
-    SelectingModeCommand(GameMod),
+    Number(usize),
+    Confirm,
     SelectMode,
+    ShowHighScores,
+    /// lines to scroll the reasoning panel by, negative scrolls up
+    Scroll(i32),
+    TogglePath,
+    ToggleLog,
     AnyKey,
 }
 
-pub fn get_command() -> Option<Command> {
-    let wait_for = time::Duration::from_millis(30);
+pub fn get_command(wait_for: time::Duration) -> Option<Command> {
     let key_event = wait_for_key_event(wait_for)?;
     match key_event.code {
         event::KeyCode::Up => Some(Command::Turn(Direction::Up)),
@@ -22,17 +30,13 @@ pub fn get_command() -> Option<Command> {
         event::KeyCode::Right => Some(Command::Turn(Direction::Right)),
         event::KeyCode::Char('q') => Some(Command::Quit),
         event::KeyCode::Char('m') => Some(Command::SelectMode),
-
-        // todo: dynamicly events based on providers enum
-        // Selecting mode
-        event::KeyCode::Char('1') => Some(Command::SelectingModeCommand(GameMod::Player)),
-
-        event::KeyCode::Char('2') => {
-            Some(Command::SelectingModeCommand(GameMod::Api(Provider::Groq)))
-        }
-        event::KeyCode::Char('3') => Some(Command::SelectingModeCommand(GameMod::Api(
-            Provider::Ollama,
-        ))),
+        event::KeyCode::Char('h') => Some(Command::ShowHighScores),
+        event::KeyCode::Char('p') => Some(Command::TogglePath),
+        event::KeyCode::Char('l') => Some(Command::ToggleLog),
+        event::KeyCode::Enter => Some(Command::Confirm),
+        event::KeyCode::PageUp => Some(Command::Scroll(-SCROLL_LINES)),
+        event::KeyCode::PageDown => Some(Command::Scroll(SCROLL_LINES)),
+        event::KeyCode::Char(c @ '1'..='9') => Some(Command::Number(c as usize - '0' as usize)),
         _ => Some(Command::AnyKey),
     }
 }
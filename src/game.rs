@@ -1,21 +1,41 @@
-use std::sync::mpsc::{Receiver, SyncSender};
+use std::collections::HashMap;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use log::*;
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use crate::client::models::OutputContent;
+use tokio_util::sync::CancellationToken;
+
+use crate::client::ensemble::MemberStats;
+use crate::client::usage::Usage;
 use crate::client::{self};
 use crate::events::Command;
-use crate::models::{GameMod, GameState, Point, Provider, RequestInfo, UIMode};
+use crate::history::{GameRecord, History};
+use crate::inspector::Inspector;
+use crate::lookahead::{PlannedPath, Projection};
+use crate::models::{Direction, GameMod, GameState, Point, Reply, RequestInfo, UIMode};
+use crate::reasoning::{Reasoning, Thought};
+use crate::registry::{Entry, Registry};
 use crate::snake::Snake;
 use crate::{events, models};
 
 pub trait Board {
     fn prepare_ui(&mut self);
-    fn render_game(&mut self, snake: &Snake, food: &Point, score: u16);
-    fn render_start_screen(&mut self);
-    fn render_game_over(&mut self, score: u16);
-    fn render_selecting_mode(&mut self);
+    /// `status` is shown next to the score, e.g. the token usage of the game
+    fn render_game(
+        &mut self,
+        snake: &Snake,
+        food: &Point,
+        score: u16,
+        status: &str,
+        panels: &Panels,
+    );
+    fn render_start_screen(&mut self, notices: &[String]);
+    fn render_game_over(&mut self, score: u16, bests: &[(String, u16)]);
+    fn render_selecting_mode(&mut self, entries: &[Entry], selected: usize, notices: &[String]);
+    fn render_high_scores(&mut self, records: &[GameRecord]);
     fn clean_up(&mut self);
     fn get_size(&self) -> (u16, u16);
     fn update_mode(&mut self, mode: UIMode);
@@ -23,25 +43,81 @@ pub trait Board {
     fn autoresize(&mut self);
 }
 
+/// what is drawn next to and over the board, the panels are shown in the llm mode
+pub struct Panels<'a> {
+    pub reasoning: &'a Reasoning,
+    /// where the queued commands lead, None when hidden
+    pub path: Option<&'a PlannedPath>,
+    pub inspector: &'a Inspector,
+    /// the log instead of the inspector
+    pub log: bool,
+}
+
+pub struct Options {
+    pub tick_rate: Duration,
+    pub seed: Option<u64>,
+    pub provider: Option<String>,
+    pub headless: bool,
+    /// send the next request before the queued commands run out
+    pub lookahead: bool,
+    pub notices: Vec<String>,
+}
+
 pub struct Game {
     board: Box<dyn Board>,
     snake: Snake,
     food: Point,
     score: u16,
-    client: Option<Provider>,
+    client: Option<String>,
     commands: Vec<models::Direction>,
     game_state: GameState,
     game_mod: GameMod,
-    tx_request: SyncSender<RequestInfo>,
-    rx_response: Receiver<OutputContent>,
+    seed: u64,
+    rng: StdRng,
+    started_at: Instant,
+    history: History,
+    registry: Registry,
+    menu_index: usize,
+    options: Options,
+    tx_request: Sender<RequestInfo>,
+    rx_reply: Receiver<Reply>,
+    /// cancels the requests of the current game
+    cancel: CancellationToken,
+    request_id: u64,
+    /// request of the current game waiting for its answer
+    pending: Option<u64>,
+    /// state the pending request was planned for when it was sent ahead of time
+    projection: Option<Projection>,
+    /// commands of the request sent ahead, queued once its projection is checked
+    next_commands: Vec<models::Direction>,
+    /// usage of the current game
+    usage: Usage,
+    /// provider of the fallback chain that plays, after its first switch
+    active: Option<String>,
+    /// switches of the fallback chain during the current game
+    switches: Vec<String>,
+    /// agreement of the ensemble members during the current game
+    members: Vec<(String, MemberStats)>,
+    /// reasoning behind the plans of the current game
+    reasoning: Reasoning,
+    /// draw where the queued commands lead
+    show_path: bool,
+    /// requests of the current game with their answers
+    inspector: Inspector,
+    show_log: bool,
+    /// usage and points of all games of the session by registry id
+    session: HashMap<String, (Usage, u32)>,
 }
 
 impl Game {
     pub fn new(
         board: Box<dyn Board>,
         snake: Snake,
-        tx_request: SyncSender<RequestInfo>,
-        rx_response: Receiver<OutputContent>,
+        history: History,
+        registry: Registry,
+        options: Options,
+        tx_request: Sender<RequestInfo>,
+        rx_reply: Receiver<Reply>,
     ) -> Self {
         Self {
             board,
@@ -52,20 +128,52 @@ impl Game {
             commands: Vec::new(),
             game_state: GameState::NotStarted,
             game_mod: GameMod::Player,
+            seed: 0,
+            rng: StdRng::seed_from_u64(0),
+            started_at: Instant::now(),
+            history,
+            registry,
+            menu_index: 0,
+            options,
             tx_request,
-            rx_response,
+            rx_reply,
+            cancel: CancellationToken::new(),
+            request_id: 0,
+            pending: None,
+            projection: None,
+            next_commands: Vec::new(),
+            usage: Usage::default(),
+            active: None,
+            members: Vec::new(),
+            switches: Vec::new(),
+            reasoning: Reasoning::default(),
+            show_path: true,
+            inspector: Inspector::default(),
+            show_log: false,
+            session: HashMap::new(),
         }
     }
 
     pub fn start(&mut self) {
         self.board.prepare_ui();
+        if let Some(provider) = self.options.provider.clone() {
+            self.select_mode(GameMod::Api(provider));
+        }
         self.new_game();
 
         loop {
-            let user_command = events::get_command();
+            let user_command = if self.options.headless {
+                thread::sleep(self.options.tick_rate);
+                None
+            } else {
+                events::get_command(self.options.tick_rate)
+            };
             if let Some(command) = &user_command {
                 match command {
-                    Command::Quit => break,
+                    Command::Quit => {
+                        self.cancel_requests();
+                        break;
+                    }
                     Command::SelectMode => {
                         self.board.update_mode(UIMode::SelectingMode);
                     }
@@ -78,18 +186,42 @@ impl Game {
                 continue;
             }
 
+            if self.board.get_mode() == UIMode::HighScores {
+                self.handle_high_scores(&user_command);
+                continue;
+            }
+            let used_by_panels = self.handle_panels(&user_command);
+
             match self.game_state {
                 GameState::NotStarted => {
-                    if user_command.is_some() {
+                    if let Some(Command::ShowHighScores) = user_command {
+                        self.board.update_mode(UIMode::HighScores);
+                        continue;
+                    }
+                    if user_command.is_some() || self.options.headless {
                         self.game_state = GameState::Running;
-                        self.board.render_game(&self.snake, &self.food, self.score);
+                        self.started_at = Instant::now();
+                        self.render_game();
                         continue;
                     }
-                    self.board.render_start_screen();
+                    self.board.render_start_screen(&self.options.notices);
                 }
                 GameState::Running => {
                     if self.crossed_borders_or_eat_itself() {
                         self.game_state = GameState::GameOver;
+                        self.record_game();
+                        if self.options.headless {
+                            println!(
+                                "Score: {}, seed: {}, duration: {}s",
+                                self.score,
+                                self.seed,
+                                self.started_at.elapsed().as_secs()
+                            );
+                            if !self.status().is_empty() {
+                                println!("Usage: {}", self.status());
+                            }
+                            break;
+                        }
                         continue;
                     };
 
@@ -98,13 +230,15 @@ impl Game {
                         self.increment_score();
                         self.snake.grow();
                     }
-                    self.board.render_game(&self.snake, &self.food, self.score);
+                    self.render_game();
                 }
                 GameState::GameOver => {
-                    self.board.render_game_over(self.score);
-                    if user_command.is_some() {
+                    self.board
+                        .render_game_over(self.score, &self.history.bests_by_provider());
+                    if user_command.is_some() && !used_by_panels {
                         self.game_state = GameState::Running;
                         self.new_game();
+                        self.started_at = Instant::now();
                         continue;
                     }
                 }
@@ -113,30 +247,23 @@ impl Game {
             match &self.game_mod {
                 GameMod::Player => {
                     if matches!(self.game_state, GameState::Running) {
-                        if let Some(command) = &user_command {
-                            match command {
-                                Command::Turn(direction) => {
-                                    self.snake.change_direction(direction.clone());
-                                }
-                                _ => (),
-                            }
-                        };
+                        if let Some(Command::Turn(direction)) = &user_command {
+                            self.snake.change_direction(direction.clone());
+                        }
                     }
                 }
                 GameMod::Api(_provider) => {
+                    self.collect_replies();
                     if matches!(self.game_state, GameState::Running) {
-                        let output = self.rx_response.try_recv();
-                        if let Ok(output) = output {
-                            for c in output.commands {
-                                for _ in 0..c.repeat {
-                                    self.commands.push(c.command.clone());
-                                }
-                            }
+                        if self.commands.is_empty() {
+                            self.check_projection();
                         }
-                        if self.commands.len() == 0 {
-                            self.do_commands_request(self.snake.get_head())
+                        if self.commands.is_empty() {
+                            self.do_commands_request()
+                        } else {
+                            self.request_ahead();
                         }
-                        if self.commands.len() > 0 {
+                        if !self.commands.is_empty() {
                             let command = self.commands.remove(0);
                             self.snake.change_direction(command);
                         } else {
@@ -150,36 +277,322 @@ impl Game {
                 self.snake.moving();
             }
         }
+        self.cancel_requests();
         self.board.clean_up();
     }
 
     fn handle_selecting_mode(&mut self, user_command: &Option<Command>) {
+        // the first row is the player mode, the rest are the registry entries
+        let rows = self.registry.entries().len() + 1;
         if let Some(command) = &user_command {
             match command {
-                Command::SelectingModeCommand(GameMod::Player) => {
-                    self.game_state = GameState::NotStarted;
-                    self.board.update_mode(UIMode::Game);
-                    self.game_mod = GameMod::Player;
+                Command::Turn(Direction::Up) => {
+                    self.menu_index = self.menu_index.saturating_sub(1);
+                }
+                Command::Turn(Direction::Down) => {
+                    self.menu_index = (self.menu_index + 1).min(rows - 1);
                 }
-                Command::SelectingModeCommand(GameMod::Api(provider)) => {
-                    self.game_state = GameState::NotStarted;
-                    self.board.update_mode(UIMode::GameWithDebug);
-                    self.game_mod = GameMod::Api(provider.clone());
-                    self.client = Some(provider.clone());
+                Command::Number(number) if *number <= rows => {
+                    self.menu_index = number - 1;
+                    self.select_menu_row(self.menu_index);
                 }
+                Command::Confirm => self.select_menu_row(self.menu_index),
                 _ => (),
             }
-        } else {
-            self.board.render_selecting_mode();
+        }
+        if self.board.get_mode() == UIMode::SelectingMode {
+            self.board.render_selecting_mode(
+                self.registry.entries(),
+                self.menu_index,
+                &self.options.notices,
+            );
         }
         self.new_game();
     }
 
+    fn select_menu_row(&mut self, row: usize) {
+        if row == 0 {
+            self.select_mode(GameMod::Player);
+            return;
+        }
+        let Some(entry) = self.registry.entries().get(row - 1) else {
+            return;
+        };
+        if entry.is_available() {
+            self.select_mode(GameMod::Api(entry.id.clone()));
+        } else {
+            warn!(
+                "{} is not configured, please provide config to config.yaml file",
+                entry.name
+            );
+        }
+    }
+
+    fn select_mode(&mut self, game_mod: GameMod) {
+        self.cancel_requests();
+        self.game_state = GameState::NotStarted;
+        match &game_mod {
+            GameMod::Player => {
+                self.board.update_mode(UIMode::Game);
+            }
+            GameMod::Api(provider) => {
+                self.board.update_mode(UIMode::GameWithDebug);
+                if self.client.as_ref() != Some(provider) {
+                    self.active = None;
+                }
+                self.client = Some(provider.clone());
+            }
+        }
+        self.game_mod = game_mod;
+    }
+
+    /// keys of the panels next to the board, the arrows and enter move in the inspector in the llm mode
+    /// true when the command was used by a panel, so it doesn't restart the game
+    fn handle_panels(&mut self, user_command: &Option<Command>) -> bool {
+        let llm = matches!(self.game_mod, GameMod::Api(_));
+        match user_command {
+            Some(Command::Scroll(lines)) => self.reasoning.scroll(*lines),
+            Some(Command::TogglePath) => self.show_path = !self.show_path,
+            Some(Command::ToggleLog) => self.show_log = !self.show_log,
+            Some(Command::Turn(Direction::Up)) if llm => self.inspector.step(-1),
+            Some(Command::Turn(Direction::Down)) if llm => self.inspector.step(1),
+            Some(Command::Confirm) if llm => self.inspector.toggle(),
+            _ => return false,
+        }
+        true
+    }
+
+    fn handle_high_scores(&mut self, user_command: &Option<Command>) {
+        if user_command.is_some() {
+            let mode = match self.game_mod {
+                GameMod::Player => UIMode::Game,
+                GameMod::Api(_) => UIMode::GameWithDebug,
+            };
+            self.board.update_mode(mode);
+        } else {
+            self.board.render_high_scores(&self.history.high_scores(10));
+        }
+    }
+
+    /// Queues the commands of the pending request, replies of cancelled requests are dropped.
+    /// The usage of every finished request counts for the session
+    fn collect_replies(&mut self) {
+        while let Ok(reply) = self.rx_reply.try_recv() {
+            match reply {
+                Reply::Commands { request, output } if self.pending == Some(request) => {
+                    let queue = if self.projection.is_some() {
+                        &mut self.next_commands
+                    } else {
+                        &mut self.commands
+                    };
+                    for c in output.commands {
+                        for _ in 0..c.repeat {
+                            queue.push(c.command.clone());
+                        }
+                    }
+                }
+                Reply::Commands { .. } => (),
+                Reply::Done {
+                    request,
+                    provider,
+                    result,
+                    usage,
+                    elapsed,
+                    switches,
+                    reasoning,
+                    transcript,
+                    members,
+                } => {
+                    let applied = self.pending == Some(request);
+                    self.inspector.finished(
+                        request,
+                        applied,
+                        &result,
+                        &usage,
+                        elapsed,
+                        transcript.map(|t| *t),
+                    );
+                    // the chain keeps its provider between requests, even cancelled ones switch it
+                    for switch in switches {
+                        self.active = Some(switch.to.clone());
+                        self.switches.push(switch.to_string());
+                    }
+                    if applied {
+                        self.usage.add(&usage);
+                        self.add_member_stats(members);
+                        self.pending = None;
+                        if let Some(text) = reasoning {
+                            self.reasoning.add(Thought {
+                                provider: provider.clone(),
+                                commands: result.map(|o| o.commands).unwrap_or_default(),
+                                text,
+                            });
+                        }
+                    }
+                    self.session.entry(provider).or_default().0.add(&usage);
+                }
+            }
+        }
+    }
+
+    /// stops the running requests, their replies are ignored
+    fn cancel_requests(&mut self) {
+        self.cancel.cancel();
+        self.cancel = CancellationToken::new();
+        self.pending = None;
+        self.projection = None;
+        self.next_commands.clear();
+    }
+
+    /// Sends the next request for the state after the queued commands,
+    /// so its answer is ready when they run out
+    fn request_ahead(&mut self) {
+        if !self.options.lookahead || self.pending.is_some() || self.projection.is_some() {
+            return;
+        }
+        let Some(projection) = Projection::new(
+            &self.snake,
+            &self.food,
+            &self.rng,
+            self.board.get_size(),
+            &self.commands,
+        ) else {
+            return;
+        };
+        debug!("Planning {} moves ahead", self.commands.len());
+        self.send_request(&projection.snake, &projection.food);
+        if self.pending.is_some() {
+            self.projection = Some(projection);
+        }
+    }
+
+    /// Queues the answer planned ahead when the snake got where it was expected,
+    /// otherwise the answer is dropped and the state is planned again
+    fn check_projection(&mut self) {
+        let Some(projection) = self.projection.take() else {
+            return;
+        };
+        if projection.holds(&self.snake, &self.food) {
+            self.commands.append(&mut self.next_commands);
+        } else {
+            info!("The game differs from the projection, planning again");
+            // nothing was sent after the request planned ahead
+            self.inspector.discard(self.request_id);
+            self.cancel_requests();
+        }
+    }
+
+    fn render_game(&mut self) {
+        let path = self.planned_path();
+        let status = self.status();
+        let panels = Panels {
+            reasoning: &self.reasoning,
+            path: path.as_ref(),
+            inspector: &self.inspector,
+            log: self.show_log,
+        };
+        self.board
+            .render_game(&self.snake, &self.food, self.score, &status, &panels);
+    }
+
+    /// where the queued commands lead, None when there are none or the path is hidden
+    fn planned_path(&self) -> Option<PlannedPath> {
+        if !self.show_path || self.commands.is_empty() {
+            return None;
+        }
+        Some(PlannedPath::new(
+            &self.snake,
+            &self.food,
+            self.board.get_size(),
+            &self.commands,
+        ))
+    }
+
+    /// usage line of the current llm game with the provider a fallback chain switched to
+    /// and the agreement of the ensemble members, empty in the player mode
+    fn status(&self) -> String {
+        let GameMod::Api(id) = &self.game_mod else {
+            return String::new();
+        };
+        let mut status = Vec::new();
+        if let Some(active) = &self.active {
+            status.push(format!("via {}", active));
+        }
+        if self.usage.calls > 0 {
+            let pricing = self.registry.get(id).and_then(|e| e.pricing.as_ref());
+            status.push(self.usage.summary(self.score as u32, pricing));
+        }
+        if !self.members.is_empty() {
+            let members: Vec<String> = self
+                .members
+                .iter()
+                .map(|(name, stats)| format!("{} {}/{} agree", name, stats.agreed, stats.answers))
+                .collect();
+            status.push(members.join(", "));
+        }
+        status.join(" | ")
+    }
+
+    fn add_member_stats(&mut self, members: Vec<(String, MemberStats)>) {
+        for (name, stats) in members {
+            match self.members.iter_mut().find(|(member, _)| *member == name) {
+                Some((_, total)) => total.add(&stats),
+                None => self.members.push((name, stats)),
+            }
+        }
+    }
+
+    fn record_game(&mut self) {
+        let (mode, provider, model, prompt) = match &self.game_mod {
+            GameMod::Player => ("player", None, None, None),
+            GameMod::Api(id) => {
+                let entry = self.registry.get(id);
+                (
+                    "llm",
+                    entry.map(|e| e.name.clone()),
+                    entry.and_then(|e| e.model.clone()),
+                    entry.and_then(|e| e.prompt.clone()),
+                )
+            }
+        };
+        let (tokens, cost) = match &self.game_mod {
+            GameMod::Api(id) => {
+                let pricing = self.registry.get(id).and_then(|e| e.pricing.clone());
+                let (session, points) = self.session.entry(id.clone()).or_default();
+                *points += self.score as u32;
+                info!(
+                    "{} this session: {}",
+                    id,
+                    session.summary(*points, pricing.as_ref())
+                );
+                (
+                    Some(self.usage.total_tokens()),
+                    pricing.map(|p| self.usage.cost(&p)),
+                )
+            }
+            GameMod::Player => (None, None),
+        };
+
+        self.history.add(GameRecord {
+            score: self.score,
+            duration_secs: self.started_at.elapsed().as_secs(),
+            mode: mode.to_string(),
+            provider,
+            model,
+            prompt,
+            tokens,
+            cost,
+            switches: self.switches.clone(),
+            seed: self.seed,
+            date: chrono::Local::now().format("%Y-%m-%d %H:%M").to_string(),
+        });
+    }
+
     fn change_food_position(&mut self) {
         let (width, height) = self.board.get_size();
         self.food = Point::new(
-            rand::thread_rng().gen_range(0..width) as i32,
-            rand::thread_rng().gen_range(0..height) as i32,
+            self.rng.gen_range(0..width) as i32,
+            self.rng.gen_range(0..height) as i32,
         );
     }
 
@@ -188,21 +601,41 @@ impl Game {
     }
 
     fn new_game(&mut self) {
-        self.snake.reset();
+        self.seed = self
+            .options
+            .seed
+            .unwrap_or_else(|| rand::thread_rng().gen());
+        self.rng = StdRng::seed_from_u64(self.seed);
+        self.snake.reset(Direction::random(&mut self.rng));
         self.commands.clear();
+        self.cancel_requests();
         self.score = 0;
+        self.usage = Usage::default();
+        self.switches.clear();
+        self.members.clear();
+        self.reasoning.clear();
+        self.inspector.clear();
 
-        self.board.render_game(&self.snake, &self.food, self.score);
+        self.render_game();
         let (width, height) = self.board.get_size();
         self.snake
             .set_head(Point::new_center(width as i32, height as i32));
 
-        self.food = Point::new_random(width as i32, height as i32);
+        self.change_food_position();
     }
 
-    fn do_commands_request(&mut self, s_head: Point) {
+    fn do_commands_request(&mut self) {
+        let snake = self.snake.clone();
+        let food = self.food.clone();
+        self.send_request(&snake, &food);
+    }
+
+    fn send_request(&mut self, snake: &Snake, food: &Point) {
+        if self.pending.is_some() {
+            return;
+        }
         if let Some(client) = self.client.clone() {
-            let food = self.food.clone();
+            let s_head = snake.get_head();
             let input = client::models::InputContent {
                 snake_head_x: s_head.x,
                 snake_head_y: s_head.y,
@@ -210,12 +643,28 @@ impl Game {
                 food_y: food.y,
             };
 
+            let (width, height) = self.board.get_size();
+            let board = client::models::BoardView {
+                width: width as i32,
+                height: height as i32,
+                occupied: snake.get_list().into_iter().collect(),
+                snake: snake.get_list().into_iter().collect(),
+                heading: Some(snake.get_direction()),
+            };
+
+            self.request_id += 1;
             let req_info = models::RequestInfo {
+                id: self.request_id,
                 provider: client,
-                input: input,
+                input,
+                board,
+                cancel: self.cancel.clone(),
             };
 
-            if let Ok(_) = self.tx_request.try_send(req_info) {
+            self.inspector
+                .sent(self.request_id, &req_info.provider, &req_info.input);
+            if self.tx_request.send(req_info).is_ok() {
+                self.pending = Some(self.request_id);
                 info!("Sending request...")
             }
         }
@@ -0,0 +1,144 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::*;
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GameRecord {
+    pub score: u16,
+    pub duration_secs: u64,
+    pub mode: String,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    /// name of the prompt template of llm games
+    #[serde(default)]
+    pub prompt: Option<String>,
+    /// tokens used by llm games
+    #[serde(default)]
+    pub tokens: Option<u64>,
+    /// estimated cost in dollars when the profile has a pricing
+    #[serde(default)]
+    pub cost: Option<f64>,
+    /// providers a fallback chain switched to during the game, e.g. "groq -> ollama: rate limited"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub switches: Vec<String>,
+    pub seed: u64,
+    pub date: String,
+}
+
+/// Results of all finished games, stored as a JSON file
+pub struct History {
+    path: Option<PathBuf>,
+    records: Vec<GameRecord>,
+}
+
+impl History {
+    /// history file in the user's data dir, e.g. ~/.local/share/snake-llm/history.json
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("snake-llm").join("history.json"))
+    }
+
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let records = match &path {
+            Some(path) if path.exists() => read_records(path).unwrap_or_else(|e| {
+                error!("Failed to read history {}: {}", path.display(), e);
+                Vec::new()
+            }),
+            _ => Vec::new(),
+        };
+
+        Self { path, records }
+    }
+
+    pub fn add(&mut self, record: GameRecord) {
+        self.records.push(record);
+        if let Err(e) = self.save() {
+            error!("Failed to save history: {}", e);
+        }
+    }
+
+    pub fn high_scores(&self, limit: usize) -> Vec<GameRecord> {
+        let mut records = self.records.clone();
+        records.sort_by(|a, b| b.score.cmp(&a.score).then(a.date.cmp(&b.date)));
+        records.truncate(limit);
+        records
+    }
+
+    /// best score for every provider, player games are listed as "Player"
+    pub fn bests_by_provider(&self) -> Vec<(String, u16)> {
+        let mut bests: Vec<(String, u16)> = Vec::new();
+        for record in &self.records {
+            let name = record
+                .provider
+                .clone()
+                .unwrap_or_else(|| "Player".to_string());
+            match bests.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, best)) => *best = (*best).max(record.score),
+                None => bests.push((name, record.score)),
+            }
+        }
+        bests.sort_by_key(|(_, best)| std::cmp::Reverse(*best));
+        bests
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let body = serde_json::to_string_pretty(&self.records).map_err(|e| e.to_string())?;
+        fs::write(path, body).map_err(|e| e.to_string())
+    }
+}
+
+fn read_records(path: &Path) -> Result<Vec<GameRecord>, String> {
+    let body = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&body).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(score: u16, provider: Option<&str>) -> GameRecord {
+        GameRecord {
+            score,
+            duration_secs: 10,
+            mode: if provider.is_some() { "llm" } else { "player" }.to_string(),
+            provider: provider.map(str::to_string),
+            model: None,
+            prompt: None,
+            tokens: None,
+            cost: None,
+            switches: Vec::new(),
+            seed: 1,
+            date: "2024-05-11 10:00".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("snake-llm-{}.json", std::process::id()));
+        let mut history = History::load(Some(path.clone()));
+        history.add(record(3, None));
+        history.add(record(7, Some("Groq")));
+        history.add(record(5, Some("Groq")));
+
+        let loaded = History::load(Some(path.clone()));
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            loaded.high_scores(2),
+            vec![record(7, Some("Groq")), record(5, Some("Groq"))]
+        );
+        assert_eq!(
+            loaded.bests_by_provider(),
+            vec![("Groq".to_string(), 7), ("Player".to_string(), 3)]
+        );
+    }
+}
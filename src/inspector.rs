@@ -0,0 +1,214 @@
+use std::{collections::VecDeque, fmt, time::Duration};
+
+use crate::client::{
+    error::ClientError,
+    models::{Commands, InputContent, OutputContent},
+    transcript::Transcript,
+    usage::Usage,
+};
+
+/// requests kept for the inspector, the older ones are dropped
+const MAX_REQUESTS: usize = 100;
+
+/// what became of the answer of a request
+#[derive(Clone, Debug, PartialEq)]
+pub enum Verdict {
+    Pending,
+    /// the commands were queued as the model wrote them
+    Applied,
+    /// the commands were queued after the parser fixed them or the model corrected them
+    Repaired,
+    /// the request was cancelled or its answer came too late
+    Discarded,
+    Fatal(String),
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::Pending => write!(f, "pending"),
+            Verdict::Applied => write!(f, "applied"),
+            Verdict::Repaired => write!(f, "repaired"),
+            Verdict::Discarded => write!(f, "discarded"),
+            Verdict::Fatal(error) => write!(f, "fatal: {}", error),
+        }
+    }
+}
+
+/// a request of the game and its answer
+#[derive(Clone, Debug)]
+pub struct Inspection {
+    pub id: u64,
+    /// local time, e.g. 12:03:04
+    pub sent_at: String,
+    pub provider: String,
+    pub input: InputContent,
+    pub commands: Vec<Commands>,
+    pub elapsed: Option<Duration>,
+    pub usage: Usage,
+    pub transcript: Transcript,
+    pub verdict: Verdict,
+}
+
+/// Requests of the current game, the newest first, with the one selected in the panel
+#[derive(Default)]
+pub struct Inspector {
+    pub requests: VecDeque<Inspection>,
+    pub selected: usize,
+    /// the selected request is shown with its whole prompt and response
+    pub expanded: bool,
+    /// lines scrolled down in the expanded request
+    pub scroll: u16,
+}
+
+impl Inspector {
+    pub fn sent(&mut self, id: u64, provider: &str, input: &InputContent) {
+        self.requests.push_front(Inspection {
+            id,
+            sent_at: chrono::Local::now().format("%H:%M:%S").to_string(),
+            provider: provider.to_string(),
+            input: input.clone(),
+            commands: Vec::new(),
+            elapsed: None,
+            usage: Usage::default(),
+            transcript: Transcript::default(),
+            verdict: Verdict::Pending,
+        });
+        self.requests.truncate(MAX_REQUESTS);
+        // the newest request stays selected unless another one was picked
+        if self.selected > 0 || self.expanded {
+            self.selected = (self.selected + 1).min(self.requests.len() - 1);
+        }
+    }
+
+    /// `applied` when the game queued the commands of the answer
+    pub fn finished(
+        &mut self,
+        id: u64,
+        applied: bool,
+        result: &Result<OutputContent, ClientError>,
+        usage: &Usage,
+        elapsed: Duration,
+        transcript: Option<Transcript>,
+    ) {
+        let Some(request) = self.requests.iter_mut().find(|r| r.id == id) else {
+            return;
+        };
+        let transcript = transcript.unwrap_or_default();
+        request.verdict = match result {
+            Err(ClientError::Cancelled) => Verdict::Discarded,
+            Err(e) => Verdict::Fatal(e.to_string()),
+            Ok(_) if !applied => Verdict::Discarded,
+            Ok(_) if !transcript.repairs.is_empty() => Verdict::Repaired,
+            Ok(_) => Verdict::Applied,
+        };
+        if let Ok(output) = result {
+            request.commands = output.commands.clone();
+        }
+        request.usage = usage.clone();
+        request.elapsed = Some(elapsed);
+        request.transcript = transcript;
+    }
+
+    /// the commands of the request were dropped after it finished
+    pub fn discard(&mut self, id: u64) {
+        if let Some(request) = self.requests.iter_mut().find(|r| r.id == id) {
+            request.verdict = Verdict::Discarded;
+        }
+    }
+
+    /// moves the selection, or scrolls the expanded request
+    pub fn step(&mut self, step: i32) {
+        if self.expanded {
+            self.scroll = (self.scroll as i32 + step).max(0) as u16;
+            return;
+        }
+        let last = self.requests.len().saturating_sub(1) as i32;
+        self.selected = (self.selected as i32 + step).clamp(0, last) as usize;
+    }
+
+    pub fn toggle(&mut self) {
+        self.expanded = !self.expanded && !self.requests.is_empty();
+        self.scroll = 0;
+    }
+
+    pub fn selected(&self) -> Option<&Inspection> {
+        self.requests.get(self.selected)
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+    use crate::models::Direction;
+
+    fn input() -> InputContent {
+        InputContent {
+            snake_head_x: 5,
+            snake_head_y: 5,
+            food_x: 5,
+            food_y: 8,
+        }
+    }
+
+    fn output() -> Result<OutputContent, ClientError> {
+        Ok(OutputContent {
+            commands: vec![Commands {
+                command: Direction::Up,
+                repeat: 3,
+            }],
+        })
+    }
+
+    #[rstest]
+    #[case(output(), true, vec![], Verdict::Applied)]
+    #[case(output(), true, vec!["removed the code fence"], Verdict::Repaired)]
+    #[case(output(), false, vec![], Verdict::Discarded)]
+    #[case(Err(ClientError::Cancelled), true, vec![], Verdict::Discarded)]
+    #[case(Err(ClientError::Other("No JSON in the answer".to_string())), true, vec![], Verdict::Fatal("No JSON in the answer".to_string()))]
+    fn test_verdict(
+        #[case] result: Result<OutputContent, ClientError>,
+        #[case] applied: bool,
+        #[case] repairs: Vec<&str>,
+        #[case] verdict: Verdict,
+    ) {
+        let mut inspector = Inspector::default();
+        inspector.sent(1, "groq", &input());
+        let transcript = Transcript {
+            repairs: repairs.into_iter().map(str::to_string).collect(),
+            ..Default::default()
+        };
+
+        inspector.finished(
+            1,
+            applied,
+            &result,
+            &Usage::default(),
+            Duration::from_millis(800),
+            Some(transcript),
+        );
+
+        assert_eq!(inspector.requests[0].verdict, verdict);
+    }
+
+    #[test]
+    fn test_selection_stays_on_the_picked_request() {
+        let mut inspector = Inspector::default();
+        inspector.sent(1, "groq", &input());
+        inspector.sent(2, "groq", &input());
+        assert_eq!(inspector.selected().unwrap().id, 2);
+
+        inspector.step(1);
+        inspector.sent(3, "groq", &input());
+
+        assert_eq!(inspector.selected().unwrap().id, 1);
+        inspector.step(-5);
+        assert_eq!(inspector.selected().unwrap().id, 3);
+    }
+}
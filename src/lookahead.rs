@@ -0,0 +1,177 @@
+use rand::{rngs::StdRng, Rng};
+
+use crate::models::{Direction, Point};
+use crate::snake::{Snake, Step};
+
+/// State of the game once the queued commands are played
+pub struct Projection {
+    pub snake: Snake,
+    pub food: Point,
+}
+
+impl Projection {
+    /// Plays the commands on copies of the snake and the food generator the same way the game does,
+    /// None when the snake doesn't survive them
+    pub fn new(
+        snake: &Snake,
+        food: &Point,
+        rng: &StdRng,
+        size: (u16, u16),
+        commands: &[Direction],
+    ) -> Option<Self> {
+        let mut snake = snake.clone();
+        let mut food = food.clone();
+        let mut rng = rng.clone();
+        for command in commands {
+            match snake.step(command, &food, (size.0 as i32, size.1 as i32), &[]) {
+                Step::Crashed(_) => return None,
+                Step::Ate => {
+                    food = Point::new(
+                        rng.gen_range(0..size.0) as i32,
+                        rng.gen_range(0..size.1) as i32,
+                    );
+                }
+                Step::Moved => {}
+            }
+        }
+        Some(Self { snake, food })
+    }
+
+    /// the answer planned for the projection is still right for the actual game
+    pub fn holds(&self, snake: &Snake, food: &Point) -> bool {
+        self.snake.get_head() == snake.get_head()
+            && self.snake.get_direction() == snake.get_direction()
+            && self.food == *food
+    }
+}
+
+/// Cells the head moves through while the queued commands are played
+#[derive(Debug, Default)]
+pub struct PlannedPath {
+    pub cells: Vec<Point>,
+    /// cell where the snake would hit a wall or its body, the last cell inside the board for a wall
+    pub crash: Option<Point>,
+}
+
+impl PlannedPath {
+    /// Plays the commands on a copy of the snake, the snake grows on the current food,
+    /// the path ends where it would die
+    pub fn new(snake: &Snake, food: &Point, size: (u16, u16), commands: &[Direction]) -> Self {
+        let mut snake = snake.clone();
+        let mut cells = Vec::new();
+        for command in commands {
+            let moved = snake.step(command, food, (size.0 as i32, size.1 as i32), &[]);
+            let head = snake.get_head();
+            if let Step::Crashed(_) = moved {
+                let crash = Point::new(
+                    head.x.clamp(0, size.0 as i32 - 1),
+                    head.y.clamp(0, size.1 as i32 - 1),
+                );
+                return Self {
+                    cells,
+                    crash: Some(crash),
+                };
+            }
+            cells.push(head);
+        }
+        Self { cells, crash: None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rstest::*;
+
+    use super::*;
+
+    fn snake(direction: Direction) -> Snake {
+        let mut snake = Snake::with_direction(direction);
+        snake.set_head(Point::new(5, 5));
+        snake
+    }
+
+    #[rstest]
+    #[case(vec![Direction::Up, Direction::Up], (5, 7), Direction::Up)]
+    #[case(vec![Direction::Right, Direction::Up], (6, 6), Direction::Up)]
+    // the snake can't turn back, it keeps going up
+    #[case(vec![Direction::Down, Direction::Down], (5, 7), Direction::Up)]
+    fn test_projected_head(
+        #[case] commands: Vec<Direction>,
+        #[case] head: (i32, i32),
+        #[case] direction: Direction,
+    ) {
+        let rng = StdRng::seed_from_u64(0);
+
+        let projection = Projection::new(
+            &snake(Direction::Up),
+            &Point::new(0, 0),
+            &rng,
+            (10, 10),
+            &commands,
+        )
+        .unwrap();
+
+        let projected = projection.snake.get_head();
+        assert_eq!((projected.x, projected.y), head);
+        assert_eq!(projection.snake.get_direction(), direction);
+    }
+
+    #[test]
+    fn test_food_eaten_on_the_way_is_replaced_like_in_the_game() {
+        let rng = StdRng::seed_from_u64(7);
+        let mut expected = rng.clone();
+        let expected = (expected.gen_range(0..10), expected.gen_range(0..10));
+
+        let projection = Projection::new(
+            &snake(Direction::Up),
+            &Point::new(5, 6),
+            &rng,
+            (10, 10),
+            &[Direction::Up, Direction::Up],
+        )
+        .unwrap();
+
+        assert_eq!((projection.food.x, projection.food.y), expected);
+        assert_eq!(projection.snake.get_list().len(), 3);
+    }
+
+    #[test]
+    fn test_no_projection_through_the_wall() {
+        let rng = StdRng::seed_from_u64(0);
+
+        let projection = Projection::new(
+            &snake(Direction::Left),
+            &Point::new(0, 0),
+            &rng,
+            (10, 10),
+            &vec![Direction::Left; 6],
+        );
+
+        assert!(projection.is_none());
+    }
+
+    #[rstest]
+    #[case(vec![Direction::Up; 3], 3, None)]
+    #[case(vec![Direction::Up; 6], 4, Some((5, 9)))]
+    // turning back into the body after a loop
+    #[case(vec![Direction::Left, Direction::Down, Direction::Right], 2, Some((5, 4)))]
+    fn test_planned_path(
+        #[case] commands: Vec<Direction>,
+        #[case] cells: usize,
+        #[case] crash: Option<(i32, i32)>,
+    ) {
+        // from 5,1 up to 5,5
+        let mut snake = Snake::with_direction(Direction::Up);
+        snake.set_head(Point::new(5, 1));
+        for _ in 0..4 {
+            snake.grow();
+            snake.moving();
+        }
+
+        let path = PlannedPath::new(&snake, &Point::new(0, 0), (10, 10), &commands);
+
+        assert_eq!(path.cells.len(), cells);
+        assert_eq!(path.crash.map(|p| (p.x, p.y)), crash);
+    }
+}
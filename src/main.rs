@@ -1,94 +1,353 @@
+mod arena;
 mod board;
+mod cli;
 mod client;
 mod config;
 mod events;
 mod game;
+mod history;
+mod inspector;
+mod lookahead;
 mod models;
+mod reasoning;
+mod registry;
 mod snake;
-use client::ApiClient;
+use clap::Parser;
+use cli::{Action, Cli};
 use config::Config;
-use models::{Provider, RequestInfo};
+use history::History;
+use models::{Reply, RequestInfo};
+use registry::Registry;
 
 use log::*;
-use std::{collections::HashMap, sync::mpsc, thread, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use tui_logger::init_logger;
 
-#[allow(unused_imports)]
+// the test templates of rstest_reuse are looked up at the crate root
+#[cfg(test)]
+#[allow(clippy::single_component_path_imports)]
 use rstest_reuse;
 
 use snake::Snake;
 
-use crate::board::BoardTUI;
+use crate::arena::{
+    tournament::{ranking_table, Tournament},
+    Arena, Entrant,
+};
+use crate::board::{headless::HeadlessBoard, BoardTUI};
+use crate::client::{
+    async_client::{AsyncClient, Outcome},
+    error::ClientError,
+    http,
+};
+use crate::game::{Board, Options};
 
 fn main() {
-    init_logger(log::LevelFilter::Trace).unwrap();
-    let config = config::parse();
+    let cli = Cli::parse();
+    let (mut config, notices) = match config::parse(&cli.config) {
+        Ok(mut config) => {
+            let errors = config.validate();
+            (config, errors)
+        }
+        Err(e) => (Config::default(), vec![e]),
+    };
+    apply_overrides(&mut config, &cli);
 
-    let mut clients: HashMap<Provider, Box<dyn ApiClient>> = HashMap::new();
-    fill_clients_map(&mut clients, &config);
+    init_logger(cli.log_level).unwrap();
+    tui_logger::set_default_level(cli.log_level);
+    if let Some(path) = &cli.log_file {
+        if let Err(e) = tui_logger::set_log_file(&path.to_string_lossy()) {
+            eprintln!("Failed to open log file {}: {}", path.display(), e);
+        }
+    }
 
-    let snake = Snake::new();
-    let board = BoardTUI::new();
-
-    let (tx_request, rx_request) = mpsc::sync_channel::<RequestInfo>(0);
-    let (tx_response, rx_response) = mpsc::sync_channel(0);
-
-    thread::spawn(move || {
-        let mut clients: HashMap<Provider, Box<dyn ApiClient>> = HashMap::new();
-        fill_clients_map(&mut clients, &config);
-
-        loop {
-            let req_info = rx_request.recv();
-
-            if let Ok(req_info) = req_info {
-                let input = req_info.input;
-
-                if let Some(client) = clients.get_mut(&req_info.provider) {
-                    let commands = client.snake_commands(input);
-
-                    match commands {
-                        Ok(res) => {
-                            info!("{:?}", res.commands);
-                            tx_response.send(res).unwrap();
-                        }
-                        Err(e) => {
-                            error!("{} \n waiting for 5 sec", e);
-                            thread::sleep(Duration::from_secs(5))
-                        }
-                    };
-                } else {
-                    error!(
-                        "No config for provider: {:?} \n Please provide config to config.yaml file",
-                        &req_info.provider.to_string()
-                    );
-                    thread::sleep(Duration::from_secs(60));
-                }
-            } else {
-                break;
+    if cli.headless || cli.action.is_some() {
+        for notice in &notices {
+            eprintln!("{}", notice);
+        }
+    }
+
+    match cli.action {
+        Some(Action::Arena) => return run_arena(&config),
+        Some(Action::Tournament) => return run_tournament(&config),
+        None => (),
+    }
+
+    let registry = Registry::from_config(&config);
+    if let Some(id) = &cli.provider {
+        match registry.get(id) {
+            Some(entry) if entry.is_available() => (),
+            Some(entry) => {
+                eprintln!(
+                    "{} is not configured, please provide config to config.yaml file",
+                    entry.name
+                );
+                return;
+            }
+            None => {
+                let ids: Vec<&str> = registry.entries().iter().map(|e| e.id.as_str()).collect();
+                eprintln!(
+                    "Unknown provider {}, expected one of: {}",
+                    id,
+                    ids.join(", ")
+                );
+                return;
             }
         }
+    }
+    let history = History::load(History::default_path());
+
+    let snake = Snake::new();
+    let board: Box<dyn Board> = match (cli.headless, cli.board_size()) {
+        (true, Some((width, height))) => Box::new(HeadlessBoard::new(width, height)),
+        (_, size) => Box::new(BoardTUI::new(size)),
+    };
+
+    let (tx_request, rx_request) = mpsc::channel::<RequestInfo>();
+    let (tx_reply, rx_reply) = mpsc::channel();
+
+    let worker = thread::spawn(move || {
+        let clients: HashMap<String, AsyncClient> = registry::create_clients(&config)
+            .into_iter()
+            .map(|(id, client)| (id, AsyncClient::new(client)))
+            .collect();
+        let runtime = http::runtime();
+        let mut tasks = Vec::new();
+
+        // every request runs in its own task, so several of them can be in flight
+        while let Ok(req_info) = rx_request.recv() {
+            let client = clients.get(&req_info.provider).cloned();
+            tasks.retain(|task: &JoinHandle<()>| !task.is_finished());
+            tasks.push(runtime.spawn(request(client, req_info, tx_reply.clone())));
+        }
+
+        // the game is over and its requests are cancelled, wait for them to stop
+        for task in tasks {
+            let _ = runtime.block_on(task);
+        }
     });
 
-    let mut game = game::Game::new(Box::new(board), snake, tx_request, rx_response);
+    let options = Options {
+        tick_rate: Duration::from_millis(cli.tick_rate),
+        seed: cli.seed,
+        provider: cli.provider,
+        headless: cli.headless,
+        lookahead: !cli.no_lookahead,
+        notices,
+    };
+
+    let mut game = game::Game::new(
+        board, snake, history, registry, options, tx_request, rx_reply,
+    );
 
     game.start();
+    drop(game);
+    if worker.join().is_err() {
+        eprintln!("The request worker stopped with a panic");
+    }
 }
 
-fn fill_clients_map(clients: &mut HashMap<Provider, Box<dyn ApiClient>>, config: &Config) {
-    if let Some(cfg) = &config.groq_client {
-        clients.insert(
-            Provider::Groq,
-            Box::new(client::groq::GroqClient::new(
-                cfg.url.clone(),
-                cfg.token.clone(),
-            )),
+/// Calls the client and sends its commands back to the game. After an error
+/// the next request waits for 5 seconds, unless the game cancels it
+async fn request(client: Option<AsyncClient>, req_info: RequestInfo, tx_reply: Sender<Reply>) {
+    let (id, cancel) = (req_info.id, req_info.cancel.clone());
+    let done = |outcome: Outcome, elapsed| Reply::Done {
+        request: id,
+        provider: req_info.provider.clone(),
+        result: outcome.result,
+        usage: outcome.usage,
+        elapsed,
+        switches: outcome.switches,
+        reasoning: outcome.reasoning,
+        transcript: outcome.transcript.map(Box::new),
+        members: outcome.members,
+    };
+
+    let Some(client) = client else {
+        error!(
+            "No config for provider: {:?} \n Please provide config to config.yaml file",
+            &req_info.provider
         );
+        wait_or_cancel(&cancel, Duration::from_secs(60)).await;
+        let error = format!("No config for provider: {}", req_info.provider);
+        let _ = tx_reply.send(done(
+            Outcome::failed(ClientError::Other(error)),
+            Duration::ZERO,
+        ));
+        return;
+    };
+
+    let sent_at = Instant::now();
+    let first_command = Arc::new(Mutex::new(None));
+    let on_commands = {
+        let (tx_reply, first_command) = (tx_reply.clone(), first_command.clone());
+        move |output| {
+            first_command
+                .lock()
+                .unwrap()
+                .get_or_insert_with(|| sent_at.elapsed());
+            let _ = tx_reply.send(Reply::Commands {
+                request: id,
+                output,
+            });
+        }
+    };
+    let outcome = client
+        .stream_snake_commands(
+            req_info.input,
+            req_info.board.clone(),
+            cancel.clone(),
+            on_commands,
+        )
+        .await;
+    let elapsed = sent_at.elapsed();
+
+    match &outcome.result {
+        Ok(res) => {
+            if let Some(elapsed) = *first_command.lock().unwrap() {
+                info!("First command in {} ms", elapsed.as_millis());
+            }
+            info!("{:?}", res.commands);
+        }
+        Err(e) if cancel.is_cancelled() => info!("{}", e),
+        Err(e) => {
+            error!("{} \n waiting for 5 sec", e);
+            wait_or_cancel(&cancel, Duration::from_secs(5)).await;
+        }
+    };
+    let _ = tx_reply.send(done(outcome, elapsed));
+}
+
+async fn wait_or_cancel(cancel: &CancellationToken, duration: Duration) {
+    tokio::select! {
+        _ = cancel.cancelled() => (),
+        _ = tokio::time::sleep(duration) => (),
     }
+}
 
-    if let Some(cfg) = &config.ollama_client {
-        clients.insert(
-            Provider::Ollama,
-            Box::new(client::ollama::OllamaClient::new(cfg.url.clone(), cfg.model.clone())),
+/// command line options take precedence over config.yaml
+fn apply_overrides(config: &mut Config, cli: &Cli) {
+    if let (Some(name), Some(model)) = (&cli.provider, &cli.model) {
+        if let Some(profile) = config.profiles.iter_mut().find(|p| p.name == *name) {
+            profile.model = model.clone();
+        }
+    }
+
+    if cli.no_cache {
+        for profile in &mut config.profiles {
+            profile.cache = false;
+        }
+    }
+
+    if let Some(seed) = cli.seed {
+        config.arena.seed = seed;
+    }
+    if let Some(width) = cli.width {
+        config.arena.width = width;
+    }
+    if let Some(height) = cli.height {
+        config.arena.height = height;
+    }
+}
+
+fn entrants(config: &Config) -> Vec<Entrant> {
+    let mut clients = registry::create_clients(config);
+
+    Registry::from_config(config)
+        .entries()
+        .iter()
+        .filter_map(|entry| {
+            let client = clients.remove(&entry.id)?;
+            Some(Entrant::new(entry.name.clone(), client).with_pricing(entry.pricing.clone()))
+        })
+        .collect()
+}
+
+fn run_arena(config: &Config) {
+    let mut entrants = entrants(config);
+    if entrants.is_empty() {
+        println!("No providers configured, please provide config to config.yaml file");
+        return;
+    }
+
+    let indexes: Vec<usize> = (0..entrants.len()).collect();
+    let result = Arena::new(&config.arena, config.arena.seed, &indexes).play(&mut entrants);
+
+    println!("Seed: {}, ticks: {}", result.seed, result.ticks);
+    for (i, standing) in result.standings.iter().enumerate() {
+        println!(
+            "{}. {} score: {}, survived: {} ticks{}",
+            i + 1,
+            entrants[standing.entrant].name,
+            standing.score,
+            standing.survived,
+            if standing.alive { " (alive)" } else { "" }
         );
     }
+    let mut points = vec![0; entrants.len()];
+    for standing in &result.standings {
+        points[standing.entrant] = standing.score as u32;
+    }
+    print_stats(&entrants, &points);
+}
+
+fn run_tournament(config: &Config) {
+    let mut entrants = entrants(config);
+    if entrants.len() < 2 {
+        println!("At least two configured providers are needed for a tournament");
+        return;
+    }
+
+    let ratings = Tournament::new(config.arena.clone(), &entrants).run(&mut entrants);
+    print!("{}", ranking_table(&ratings));
+    let points: Vec<u32> = entrants
+        .iter()
+        .map(|e| {
+            ratings
+                .iter()
+                .find(|r| r.name == e.name)
+                .map_or(0, |r| r.points)
+        })
+        .collect();
+    print_stats(&entrants, &points);
+}
+
+/// `points` are the points of every entrant in the match or tournament
+fn print_stats(entrants: &[Entrant], points: &[u32]) {
+    for (entrant, points) in entrants.iter().zip(points) {
+        let client = entrant.client.lock();
+        let usage = client.usage();
+        if usage.calls > 0 {
+            println!(
+                "{}: {}",
+                entrant.name,
+                usage.summary(*points, entrant.pricing.as_ref())
+            );
+        }
+        let plans = client.plan_stats();
+        if plans.answers > 0 {
+            println!("{}: {}", entrant.name, plans);
+        }
+        let stats = client.correction_stats();
+        if stats.asked > 0 {
+            println!("{}: {}", entrant.name, stats);
+        }
+        let cache = client.cache_stats();
+        if cache.hits + cache.misses > 0 {
+            println!("{}: {}", entrant.name, cache);
+        }
+        for (member, stats) in client.member_stats() {
+            println!("{} / {}: {}", entrant.name, member, stats);
+        }
+    }
 }
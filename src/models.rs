@@ -3,17 +3,32 @@ pub enum UIMode {
     Game,
     GameWithDebug,
     SelectingMode,
+    HighScores,
 }
 
 pub enum GameMod {
     Player,
-    Api(Provider),
+    /// controlled by the provider with the registry id
+    Api(String),
 }
 
-#[derive(PartialEq, Eq, Hash, Clone, EnumIter, Display)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug, EnumIter, Display, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Provider {
     Groq,
     Ollama,
+    /// native `/completion` endpoint of llama-server
+    LlamaCpp,
+    /// Anthropic Messages API
+    Anthropic,
+    /// Gemini generateContent API
+    Gemini,
+}
+
+impl Provider {
+    pub fn id(&self) -> String {
+        self.to_string().to_lowercase()
+    }
 }
 
 pub enum GameState {
@@ -23,11 +38,35 @@ pub enum GameState {
 }
 
 pub struct RequestInfo {
-    pub provider: Provider,
+    /// number of the request, sent back with the replies
+    pub id: u64,
+    pub provider: String,
     pub input: InputContent,
+    pub board: BoardView,
+    /// cancelled when the game or the mode changes
+    pub cancel: CancellationToken,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// what the worker sends back for a request
+pub enum Reply {
+    /// commands parsed so far, streaming clients send several
+    Commands { request: u64, output: OutputContent },
+    /// the request is finished, with the usage of its calls, the switches of a fallback chain,
+    /// the reasoning and transcript of the model for the panels and the agreement of the ensemble members
+    Done {
+        request: u64,
+        provider: String,
+        result: Result<OutputContent, ClientError>,
+        usage: Usage,
+        elapsed: Duration,
+        switches: Vec<Switch>,
+        reasoning: Option<String>,
+        transcript: Option<Box<Transcript>>,
+        members: Vec<(String, MemberStats)>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, EnumIter)]
 #[serde(rename_all = "snake_case")]
 pub enum Direction {
     Up,
@@ -37,6 +76,25 @@ pub enum Direction {
 }
 
 impl Direction {
+    pub fn random(rng: &mut impl Rng) -> Direction {
+        match rng.gen_range(0..4) {
+            0 => Direction::Up,
+            1 => Direction::Down,
+            2 => Direction::Left,
+            _ => Direction::Right,
+        }
+    }
+
+    /// change of the head coordinates after one move
+    pub fn delta(&self) -> (i32, i32) {
+        match self {
+            Direction::Up => (0, 1),
+            Direction::Down => (0, -1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        }
+    }
+
     pub fn opposite(&self) -> Direction {
         match self {
             Direction::Up => Direction::Down,
@@ -47,13 +105,24 @@ impl Direction {
     }
 }
 
+use std::time::Duration;
+
 use rand::Rng;
 use serde_derive::{Deserialize, Serialize};
 use strum::{Display, EnumIter};
 
-use crate::client::models::InputContent;
+use tokio_util::sync::CancellationToken;
+
+use crate::client::{
+    ensemble::MemberStats,
+    error::ClientError,
+    fallback::Switch,
+    models::{BoardView, InputContent, OutputContent},
+    transcript::Transcript,
+    usage::Usage,
+};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Point {
     pub x: i32,
     pub y: i32,
@@ -64,11 +133,6 @@ impl Point {
         Self { x, y }
     }
 
-    pub fn new_random(width: i32, height: i32) -> Self {
-        let mut rng = rand::thread_rng();
-        Self::new(rng.gen_range(0..width), rng.gen_range(0..height))
-    }
-
     pub fn new_center(width: i32, height: i32) -> Self {
         Self::new(width / 2, height / 2)
     }
@@ -0,0 +1,100 @@
+use std::collections::VecDeque;
+
+use crate::client::models::Commands;
+
+/// thoughts kept for the panel, the older ones are dropped
+const MAX_THOUGHTS: usize = 50;
+
+/// reasoning the model wrote before a plan
+#[derive(Clone, Debug)]
+pub struct Thought {
+    pub provider: String,
+    /// the plan that followed, empty when the answer had none
+    pub commands: Vec<Commands>,
+    pub text: String,
+}
+
+impl Thought {
+    /// e.g. "groq: up 3, left 2"
+    pub fn title(&self) -> String {
+        let plan = if self.commands.is_empty() {
+            "no plan".to_string()
+        } else {
+            self.commands
+                .iter()
+                .map(|c| format!("{:?} {}", c.command, c.repeat).to_lowercase())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        format!("{}: {}", self.provider, plan)
+    }
+}
+
+/// Reasoning behind the plans of the current game, the newest first
+#[derive(Default)]
+pub struct Reasoning {
+    pub thoughts: VecDeque<Thought>,
+    /// lines of the text scrolled down from the top of the newest thought
+    pub scroll: u16,
+}
+
+impl Reasoning {
+    /// shows the new thought at the top
+    pub fn add(&mut self, thought: Thought) {
+        self.thoughts.push_front(thought);
+        self.thoughts.truncate(MAX_THOUGHTS);
+        self.scroll = 0;
+    }
+
+    pub fn scroll(&mut self, lines: i32) {
+        let last = self.lines().saturating_sub(1) as i32;
+        self.scroll = (self.scroll as i32 + lines).clamp(0, last) as u16;
+    }
+
+    pub fn clear(&mut self) {
+        self.thoughts.clear();
+        self.scroll = 0;
+    }
+
+    /// lines of the panel before wrapping, a title, the text and a blank line per thought
+    fn lines(&self) -> usize {
+        self.thoughts
+            .iter()
+            .map(|t| t.text.lines().count() + 2)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Direction;
+
+    fn thought(text: &str) -> Thought {
+        Thought {
+            provider: "groq".to_string(),
+            commands: vec![Commands {
+                command: Direction::Up,
+                repeat: 3,
+            }],
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_scroll_stays_in_the_text() {
+        let mut reasoning = Reasoning::default();
+        reasoning.add(thought("first\nsecond"));
+        reasoning.add(thought("third"));
+
+        reasoning.scroll(10);
+        assert_eq!(reasoning.scroll, 6);
+        reasoning.scroll(-20);
+        assert_eq!(reasoning.scroll, 0);
+
+        reasoning.scroll(2);
+        reasoning.add(thought("fourth"));
+        assert_eq!(reasoning.scroll, 0);
+        assert_eq!(reasoning.thoughts[0].title(), "groq: up 3");
+    }
+}
@@ -0,0 +1,344 @@
+use std::{collections::HashMap, time::Duration};
+
+use strum::IntoEnumIterator;
+
+use crate::client::{
+    anthropic::AnthropicApi,
+    async_client::AsyncClient,
+    bot::BotClient,
+    cache::{self, CachedClient},
+    chat::{ChatApi, ChatClient},
+    ensemble::Ensemble,
+    fallback::{self, Fallback},
+    few_shot::{self, ExamplePicker, FewShot},
+    gemini::GeminiApi,
+    groq::GroqApi,
+    llama_cpp::LlamaCppApi,
+    models::Protocol,
+    ollama::OllamaApi,
+    prompt::PromptTemplate,
+    tools::{ToolApi, ToolClient},
+    usage::Pricing,
+    ApiClient,
+};
+use crate::config::{Config, EnsembleProfile, FallbackProfile, Profile};
+use crate::models::Provider;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status {
+    Available,
+    NotConfigured,
+}
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub id: String,
+    pub name: String,
+    pub provider: Provider,
+    pub model: Option<String>,
+    /// name of the prompt template
+    pub prompt: Option<String>,
+    /// dollars per million tokens of the model
+    pub pricing: Option<Pricing>,
+    pub status: Status,
+}
+
+impl Entry {
+    pub fn is_available(&self) -> bool {
+        self.status == Status::Available
+    }
+}
+
+/// All profiles known to the game, built from the config at startup.
+/// Providers without any profile are listed as not configured.
+/// The order of the entries is the order of the selection menu.
+pub struct Registry {
+    entries: Vec<Entry>,
+}
+
+impl Registry {
+    pub fn from_config(config: &Config) -> Self {
+        let mut entries: Vec<Entry> = config
+            .profiles
+            .iter()
+            .map(|profile| Entry {
+                id: profile.name.clone(),
+                name: profile.name.clone(),
+                provider: profile.provider.clone(),
+                model: Some(profile.model.clone()),
+                prompt: Some(prompt_name(profile)),
+                pricing: profile.pricing.clone(),
+                status: Status::Available,
+            })
+            .collect();
+
+        for ensemble in &config.ensembles {
+            let first = config
+                .profiles
+                .iter()
+                .find(|p| p.name == ensemble.members[0]);
+            entries.push(Entry {
+                id: ensemble.name.clone(),
+                name: ensemble.name.clone(),
+                provider: first
+                    .map(|p| p.provider.clone())
+                    .unwrap_or(Provider::Ollama),
+                model: Some(format!(
+                    "{} of {}",
+                    ensemble.strategy,
+                    ensemble.members.join(", ")
+                )),
+                prompt: None,
+                pricing: None,
+                status: Status::Available,
+            });
+        }
+
+        for chain in &config.fallbacks {
+            let first = config.profiles.iter().find(|p| p.name == chain.chain[0]);
+            entries.push(Entry {
+                id: chain.name.clone(),
+                name: chain.name.clone(),
+                provider: first
+                    .map(|p| p.provider.clone())
+                    .unwrap_or(Provider::Ollama),
+                model: Some(chain.chain.join(" -> ")),
+                prompt: first.map(prompt_name),
+                pricing: None,
+                status: Status::Available,
+            });
+        }
+
+        for provider in Provider::iter() {
+            if !entries.iter().any(|e| e.provider == provider) {
+                entries.push(Entry {
+                    id: provider.id(),
+                    name: provider.to_string(),
+                    provider,
+                    model: None,
+                    prompt: None,
+                    pricing: None,
+                    status: Status::NotConfigured,
+                });
+            }
+        }
+
+        Self { entries }
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.id == id)
+    }
+}
+
+fn template(profile: &Profile) -> PromptTemplate {
+    profile
+        .template
+        .clone()
+        .unwrap_or_else(|| PromptTemplate::builtin(&profile.prompt))
+}
+
+fn prompt_name(profile: &Profile) -> String {
+    match profile.protocol {
+        Protocol::Json => match &profile.few_shot {
+            Some(f) => format!("{}+{}", template(profile).name, f.name()),
+            None => template(profile).name,
+        },
+        Protocol::Tools => "tools".to_string(),
+    }
+}
+
+/// clients of the configured profiles and ensembles keyed by the registry id
+pub fn create_clients(config: &Config) -> HashMap<String, Box<dyn ApiClient>> {
+    let mut clients: HashMap<String, Box<dyn ApiClient>> = config
+        .profiles
+        .iter()
+        .map(|profile| (profile.name.clone(), create_client(profile)))
+        .collect();
+    for ensemble in &config.ensembles {
+        clients.insert(ensemble.name.clone(), create_ensemble(config, ensemble));
+    }
+    for chain in &config.fallbacks {
+        clients.insert(chain.name.clone(), create_fallback(config, chain));
+    }
+    clients
+}
+
+/// the members get their own clients, so they don't share the history with the single profiles
+fn create_ensemble(config: &Config, ensemble: &EnsembleProfile) -> Box<dyn ApiClient> {
+    let members = ensemble
+        .members
+        .iter()
+        .filter_map(|name| config.profiles.iter().find(|p| p.name == *name))
+        .map(|profile| {
+            (
+                profile.name.clone(),
+                AsyncClient::new(create_client(profile)),
+            )
+        })
+        .collect();
+    Box::new(Ensemble::new(ensemble.strategy.clone(), members))
+}
+
+/// the client of the profile, behind the response cache when it's enabled
+fn create_client(profile: &Profile) -> Box<dyn ApiClient> {
+    let client = create_model_client(profile);
+    if !profile.cache {
+        return client;
+    }
+    Box::new(CachedClient::new(
+        client,
+        cache_key(profile),
+        cache::default_path(),
+    ))
+}
+
+/// Provider and model with a hash of the settings that change the answers:
+/// the templates and examples of the prompt, the few-shot settings and the temperature
+fn cache_key(profile: &Profile) -> String {
+    let template = template(profile);
+    let mut settings = vec![prompt_name(profile), template.system, template.user];
+    for example in template.examples {
+        settings.push(example.user);
+        settings.push(example.assistant);
+    }
+    settings.push(format!("{:?}", profile.temperature));
+    format!(
+        "{}|{}|{:016x}",
+        profile.provider.id(),
+        profile.model,
+        cache::fingerprint(&settings.join("\n"))
+    )
+}
+
+fn create_model_client(profile: &Profile) -> Box<dyn ApiClient> {
+    if profile.protocol == Protocol::Tools {
+        return create_tool_client(profile);
+    }
+    let (url, token, model) = (
+        profile.url.clone(),
+        profile.token.clone(),
+        profile.model.clone(),
+    );
+    match profile.provider {
+        Provider::Groq => create_chat_client(GroqApi::new(url, token, Some(model)), profile),
+        Provider::Ollama => create_chat_client(OllamaApi::new(url, model), profile),
+        Provider::LlamaCpp => create_chat_client(LlamaCppApi::new(url, model), profile),
+        Provider::Anthropic => create_chat_client(AnthropicApi::new(url, token, model), profile),
+        Provider::Gemini => create_chat_client(GeminiApi::new(url, token, model), profile),
+    }
+}
+
+/// conversation with the model over the API of its provider, with the settings of the profile
+fn create_chat_client<A: ChatApi + 'static>(api: A, profile: &Profile) -> Box<dyn ApiClient> {
+    let mut client = ChatClient::new(api)
+        .with_template(template(profile))
+        .with_stream(profile.stream)
+        .with_structured_output(profile.structured_output)
+        .with_max_corrections(profile.max_corrections)
+        .with_history(profile.history.clone());
+    if let Some(temperature) = profile.temperature {
+        client = client.with_temperature(temperature);
+    }
+    if let Some(timeout) = profile.timeout_secs {
+        client = client.with_timeout(Duration::from_secs(timeout));
+    }
+    if let Some(tokens) = profile.context_tokens {
+        client = client.with_context_tokens(tokens);
+    }
+    if let Some(f) = &profile.few_shot {
+        client = client.with_few_shot(example_picker(f));
+    }
+    Box::new(client)
+}
+
+fn create_fallback(config: &Config, chain: &FallbackProfile) -> Box<dyn ApiClient> {
+    let clients = chain
+        .chain
+        .iter()
+        .filter_map(|name| {
+            let client: Box<dyn ApiClient> = if name == fallback::BOT {
+                Box::new(BotClient::default())
+            } else {
+                create_client(config.profiles.iter().find(|p| p.name == *name)?)
+            };
+            Some((name.clone(), client))
+        })
+        .collect();
+    let mut client = Fallback::new(clients, chain.switch_on.clone())
+        .with_retry_after(Duration::from_secs(chain.retry_secs));
+    if let Some(timeout) = chain.timeout_secs {
+        client = client.with_timeout(Duration::from_secs(timeout));
+    }
+    Box::new(client)
+}
+
+fn example_picker(few_shot: &FewShot) -> ExamplePicker {
+    ExamplePicker::new(few_shot.clone(), few_shot::default_store_path())
+}
+
+fn create_tool_client(profile: &Profile) -> Box<dyn ApiClient> {
+    let api = match profile.provider {
+        // llama_cpp, anthropic and gemini are rejected by the config validation,
+        // llama-server has tools only on its OpenAI endpoint
+        Provider::Groq | Provider::LlamaCpp | Provider::Anthropic | Provider::Gemini => {
+            ToolApi::OpenAi
+        }
+        Provider::Ollama => ToolApi::Ollama,
+    };
+    let mut client = ToolClient::new(
+        api,
+        profile.url.clone(),
+        profile.token.clone(),
+        profile.model.clone(),
+    );
+    if let Some(temperature) = profile.temperature {
+        client = client.with_temperature(temperature);
+    }
+    if let Some(timeout) = profile.timeout_secs {
+        client = client.with_timeout(Duration::from_secs(timeout));
+    }
+    Box::new(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{few_shot::ExampleSource, models::PromptVariant};
+
+    #[test]
+    fn test_cache_key_changes_with_the_prompt_settings() {
+        let profile: Profile = serde_json::from_value(serde_json::json!({
+            "name": "phi3",
+            "provider": "ollama",
+            "model": "phi3",
+        }))
+        .unwrap();
+        let key = cache_key(&profile);
+
+        let mut warmer = profile.clone();
+        warmer.temperature = Some(0.7);
+        let mut compact = profile.clone();
+        compact.prompt = PromptVariant::Compact;
+        let mut few_shot = profile.clone();
+        few_shot.few_shot = Some(FewShot {
+            source: ExampleSource::Bot,
+            examples: 3,
+        });
+        // the same template name with another system prompt
+        let mut edited = profile.clone();
+        edited.template = Some(PromptTemplate {
+            system: "Eat the food.".to_string(),
+            ..template(&profile)
+        });
+
+        assert_eq!(cache_key(&profile.clone()), key);
+        for changed in [warmer, compact, few_shot, edited] {
+            assert_ne!(cache_key(&changed), key);
+        }
+    }
+}
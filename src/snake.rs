@@ -1,9 +1,25 @@
-use rand::Rng;
-
 use std::collections::LinkedList;
 
 use crate::models::{Direction, Point};
 
+/// what happened to the snake in one tick of the game
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    Moved,
+    /// the head got to the food, the snake grows
+    Ate,
+    Crashed(Crash),
+}
+
+/// cell the head moved to when the snake died
+#[derive(Debug, PartialEq)]
+pub enum Crash {
+    /// outside the board
+    Wall(Point),
+    /// taken by its body or another snake
+    Snake(Point),
+}
+
 #[derive(Clone)]
 pub struct Snake {
     list: LinkedList<Point>,
@@ -15,16 +31,29 @@ impl Snake {
     pub fn new() -> Self {
         Self {
             list: LinkedList::new(),
-            direction: match rand::thread_rng().gen_range(0..4) {
-                0 => Direction::Up,
-                1 => Direction::Down,
-                2 => Direction::Left,
-                _ => Direction::Right,
-            },
+            direction: Direction::random(&mut rand::thread_rng()),
+            length: 2,
+        }
+    }
+
+    pub fn with_direction(direction: Direction) -> Self {
+        Self {
+            list: LinkedList::new(),
+            direction,
             length: 2,
         }
     }
 
+    /// snake on the cells from the head to the tail, it keeps its length until it eats
+    pub fn from_cells(cells: impl IntoIterator<Item = Point>, direction: Direction) -> Self {
+        let list: LinkedList<Point> = cells.into_iter().collect();
+        Self {
+            length: list.len(),
+            list,
+            direction,
+        }
+    }
+
     pub fn set_head(&mut self, point: Point) {
         self.list.push_front(point);
     }
@@ -48,6 +77,41 @@ impl Snake {
         }
     }
 
+    /// One tick of the game: turns unless the command reverses the snake, moves and grows
+    /// on the food. The tail leaves its cell before the head moves in, unless the snake grows.
+    /// `others` are the cells of the other snakes
+    pub fn step(
+        &mut self,
+        command: &Direction,
+        food: &Point,
+        size: (i32, i32),
+        others: &[Point],
+    ) -> Step {
+        self.change_direction(command.clone());
+        self.moving();
+        if let Some(crash) = self.crash(size, others) {
+            return Step::Crashed(crash);
+        }
+        if self.get_head() == *food {
+            self.grow();
+            return Step::Ate;
+        }
+        Step::Moved
+    }
+
+    /// how the head left the board or ran into the body or one of `others`, None while it's fine
+    pub fn crash(&self, (width, height): (i32, i32), others: &[Point]) -> Option<Crash> {
+        let mut cells = self.list.iter();
+        let head = cells.next()?;
+        if head.x < 0 || head.y < 0 || head.x >= width || head.y >= height {
+            return Some(Crash::Wall(head.clone()));
+        }
+        if cells.chain(others).any(|p| p == head) {
+            return Some(Crash::Snake(head.clone()));
+        }
+        None
+    }
+
     pub fn grow(&mut self) {
         self.length += 1;
     }
@@ -62,12 +126,70 @@ impl Snake {
         self.list.clone()
     }
 
+    pub fn get_direction(&self) -> Direction {
+        self.direction.clone()
+    }
+
     pub fn get_head(&self) -> Point {
         self.list.front().unwrap().clone()
     }
 
-    pub fn reset(&mut self) {
+    pub fn reset(&mut self, direction: Direction) {
         self.list.clear();
         self.length = 2;
+        self.direction = direction;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[case(vec![Direction::Right], Step::Moved)]
+    // the snake can't turn back, it keeps going right
+    #[case(vec![Direction::Left], Step::Moved)]
+    #[case(vec![Direction::Up], Step::Ate)]
+    #[case(vec![Direction::Down], Step::Crashed(Crash::Snake(Point::new(2, 0))))]
+    #[case(vec![Direction::Right, Direction::Right], Step::Crashed(Crash::Wall(Point::new(4, 1))))]
+    fn test_step(#[case] commands: Vec<Direction>, #[case] expected: Step) {
+        // on a 4x3 board, moving right from 0,1 to 2,1
+        let mut snake = Snake::from_cells(
+            [Point::new(2, 1), Point::new(1, 1), Point::new(0, 1)],
+            Direction::Right,
+        );
+        let food = Point::new(2, 2);
+        let others = [Point::new(2, 0)];
+
+        let steps: Vec<Step> = commands
+            .iter()
+            .map(|command| snake.step(command, &food, (4, 3), &others))
+            .collect();
+
+        assert_eq!(steps.last(), Some(&expected));
+    }
+
+    #[rstest]
+    #[case(Point::new(3, 2), Step::Moved)]
+    // the snake grows on the food, the tail stays for one tick
+    #[case(Point::new(0, 1), Step::Crashed(Crash::Snake(Point::new(1, 1))))]
+    fn test_head_follows_the_tail(#[case] food: Point, #[case] expected: Step) {
+        // the head moves up and right into the cell of the tail
+        let mut snake = Snake::from_cells(
+            [
+                Point::new(0, 0),
+                Point::new(1, 0),
+                Point::new(1, 1),
+                Point::new(1, 2),
+            ],
+            Direction::Left,
+        );
+
+        snake.step(&Direction::Up, &food, (4, 3), &[]);
+        let moved = snake.step(&Direction::Right, &food, (4, 3), &[]);
+
+        assert_eq!(moved, expected);
     }
 }